@@ -0,0 +1,46 @@
+//! Helpers shared by the benchmarks, which are plain binaries run with
+//! `cargo bench` so they build on stable.
+
+use std::time::{Duration, Instant};
+
+/// Calls `run` for about a second and prints the mean time per call.
+/// `run` returns something computed from its result, such as a length,
+/// so the work can't be optimised away.
+pub fn bench<F: FnMut() -> usize>(name: &str, mut run: F) {
+    let mut check = run();
+    let start = Instant::now();
+    let mut runs = 0u32;
+    while start.elapsed() < Duration::from_secs(1) {
+        check = check.wrapping_add(run());
+        runs += 1;
+    }
+    println!(
+        "{:<36} {:>12.2?} per run ({} runs, check {})",
+        name,
+        start.elapsed() / runs,
+        runs,
+        check % 10
+    );
+}
+
+/// An object with `count` records in an array, the kind of document the
+/// fast paths are meant for.
+pub fn records(count: usize) -> String {
+    let records: Vec<String> = (0..count)
+        .map(|i| {
+            format!(
+                "{{\"id\": {}, \"name\": \"record {}\", \"score\": {}.5, \"tags\": [\"a\", \"b\\\"c\"], \"ok\": true}}",
+                i, i, i
+            )
+        })
+        .collect();
+    format!(
+        "{{\"title\": \"big\", \"records\": [{}]}}",
+        records.join(", ")
+    )
+}
+
+/// The contents of a file in `test_files`.
+pub fn test_file(name: &str) -> String {
+    std::fs::read_to_string(format!("./test_files/{}", name)).unwrap()
+}
@@ -0,0 +1,46 @@
+//! Looks up every key of objects of growing size, through `get_value` and
+//! through a plain linear search, to show where indexing starts to pay off
+//! (`SMALL_OBJECT_LEN`), and parses the test files to show what building
+//! the index costs.
+
+mod common;
+
+use common::{bench, records, test_file};
+use rson::types::JsonObject;
+
+fn object(len: usize) -> (JsonObject, Vec<String>) {
+    let keys: Vec<String> = (0..len).map(|i| format!("member_{}", i)).collect();
+    let members: Vec<String> = keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| format!("\"{}\": {}", key, i))
+        .collect();
+    let object = rson::parse(format!("{{{}}}", members.join(", ")));
+    (object, keys)
+}
+
+fn main() {
+    for &len in &[2, 4, 8, 12, 16, 20, 24, 28, 32, 128] {
+        let (object, keys) = object(len);
+        println!("{} members", len);
+        bench("  get_value", || {
+            keys.iter()
+                .filter(|key| object.get_value(key).is_some())
+                .count()
+        });
+        bench("  linear search", || {
+            keys.iter()
+                .filter(|key| object.to_iter().any(|(entry, _)| entry == key.as_str()))
+                .count()
+        });
+    }
+
+    for name in &["2.json", "mixed.json", "test_package.json"] {
+        let json = test_file(name);
+        bench(&format!("parse {}", name), || {
+            rson::parse(json.clone()).len()
+        });
+    }
+    let json = records(10_000);
+    bench("parse 10000 records", || rson::parse(json.clone()).len());
+}
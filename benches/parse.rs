@@ -0,0 +1,25 @@
+//! Compares `structural::parse` with `rson::parse`. Both are given an
+//! owned copy of the input, as `rson::parse` takes a `String`.
+
+mod common;
+
+use common::{bench, records, test_file};
+use rson::structural;
+
+fn main() {
+    let inputs = vec![
+        (
+            "test_package.json".to_string(),
+            test_file("test_package.json"),
+        ),
+        ("10000 records".to_string(), records(10_000)),
+    ];
+
+    for (name, json) in &inputs {
+        println!("{} ({} bytes)", name, json.len());
+        bench("  rson::parse", || rson::parse(json.clone()).len());
+        bench("  structural::parse", || {
+            structural::parse(&json.clone()).unwrap().len()
+        });
+    }
+}
@@ -0,0 +1,312 @@
+//! The array type of `JsonValue::Vec`.
+
+use std::cmp::Ordering;
+use std::iter::FromIterator;
+use std::ops::{Deref, DerefMut};
+use std::{slice, vec};
+
+use super::error::Error;
+use super::pointer;
+use super::types::{JsonNum, JsonObject, JsonValue, TypeMismatch};
+
+/// The elements of a JSON array. Dereferences to a slice, so indexing,
+/// `len`, `iter`, `sort_by` and the rest of the slice methods work on it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JsonArray {
+    elements: Vec<JsonValue>,
+}
+
+impl JsonArray {
+    pub fn new() -> JsonArray {
+        JsonArray::default()
+    }
+
+    pub fn into_vec(self) -> Vec<JsonValue> {
+        self.elements
+    }
+
+    pub fn push<T: Into<JsonValue>>(&mut self, value: T) {
+        self.elements.push(value.into());
+    }
+
+    /// Inserts at `index`, moving the elements from there on back. Panics
+    /// if `index` is past the end.
+    pub fn insert<T: Into<JsonValue>>(&mut self, index: usize, value: T) {
+        self.elements.insert(index, value.into());
+    }
+
+    /// Removes the element at `index`, or returns `None` if there is none.
+    pub fn remove(&mut self, index: usize) -> Option<JsonValue> {
+        if index < self.elements.len() {
+            Some(self.elements.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.elements.truncate(len);
+    }
+
+    /// The first element `predicate` returns true for.
+    pub fn find<P>(&self, mut predicate: P) -> Option<&JsonValue>
+    where
+        P: FnMut(&JsonValue) -> bool,
+    {
+        self.elements.iter().find(|value| predicate(value))
+    }
+
+    /// Removes consecutive equal elements, like `Vec::dedup`, objects being
+    /// equal if they have the same members in any order. Takes linear time.
+    pub fn dedup(&mut self) {
+        self.elements.dedup();
+    }
+
+    /// Removes every element equal to an earlier one, wherever it is, unlike
+    /// `dedup`. Compares each element with all those kept before it, so this
+    /// takes quadratic time, as values can't be hashed or fully ordered.
+    pub fn dedup_all(&mut self) {
+        let mut kept: Vec<JsonValue> = Vec::with_capacity(self.elements.len());
+        for value in self.elements.drain(..) {
+            if !kept.contains(&value) {
+                kept.push(value);
+            }
+        }
+        self.elements = kept;
+    }
+
+    /// Sorts the elements by the value at a JSON Pointer inside each of
+    /// them, such as `/name` to sort objects by their name. The sort is
+    /// stable, and elements without a value there sort as `null`.
+    ///
+    /// Values of different types are ordered null, booleans, numbers,
+    /// strings, arrays, objects; arrays compare element by element, and
+    /// all objects compare equal.
+    ///
+    /// Fails with `Error::InvalidPointer`, leaving the array as it was, if
+    /// `pointer` is neither empty nor starts with a slash.
+    pub fn sort_by_pointer(&mut self, pointer: &str) -> Result<(), Error> {
+        let tokens = pointer::split(pointer)?;
+        self.elements.sort_by(|first, second| {
+            let first = first.get_tokens(&tokens).unwrap_or(&JsonValue::Null);
+            let second = second.get_tokens(&tokens).unwrap_or(&JsonValue::Null);
+            compare(first, second)
+        });
+        Ok(())
+    }
+
+    /// Converts each element with one of the `JsonValue::as_*` methods,
+    /// reporting its index as the key of a mismatch.
+    fn iter_as<'a, T: 'a>(
+        &'a self,
+        expected: &'static str,
+        convert: fn(&'a JsonValue) -> Option<T>,
+    ) -> impl Iterator<Item = Result<T, TypeMismatch>> + 'a {
+        self.elements.iter().enumerate().map(move |(index, value)| {
+            convert(value).ok_or_else(|| TypeMismatch {
+                key: index.to_string(),
+                expected,
+                actual: value.kind_name(),
+            })
+        })
+    }
+
+    pub fn iter_str(&self) -> impl Iterator<Item = Result<&str, TypeMismatch>> {
+        self.iter_as("string", JsonValue::as_str)
+    }
+
+    pub fn iter_i64(&self) -> impl Iterator<Item = Result<i64, TypeMismatch>> + '_ {
+        self.iter_as("integer", JsonValue::as_i64)
+    }
+
+    pub fn iter_u64(&self) -> impl Iterator<Item = Result<u64, TypeMismatch>> + '_ {
+        self.iter_as("unsigned integer", JsonValue::as_u64)
+    }
+
+    pub fn iter_f64(&self) -> impl Iterator<Item = Result<f64, TypeMismatch>> + '_ {
+        self.iter_as("number", JsonValue::as_f64)
+    }
+
+    pub fn iter_bool(&self) -> impl Iterator<Item = Result<bool, TypeMismatch>> + '_ {
+        self.iter_as("boolean", JsonValue::as_bool)
+    }
+
+    pub fn iter_array(&self) -> impl Iterator<Item = Result<&JsonArray, TypeMismatch>> {
+        self.iter_as("array", JsonValue::as_array)
+    }
+
+    pub fn iter_object(&self) -> impl Iterator<Item = Result<&JsonObject, TypeMismatch>> {
+        self.iter_as("object", JsonValue::as_object)
+    }
+}
+
+fn type_rank(value: &JsonValue) -> u8 {
+    match value {
+        JsonValue::Null => 0,
+        JsonValue::Bool(_) => 1,
+        JsonValue::Num(_) => 2,
+        JsonValue::String(_) => 3,
+        JsonValue::Vec(_) => 4,
+        JsonValue::Object(_) => 5,
+    }
+}
+
+/// The order `sort_by_pointer` sorts in.
+fn compare(first: &JsonValue, second: &JsonValue) -> Ordering {
+    match (first, second) {
+        (JsonValue::Bool(first), JsonValue::Bool(second)) => first.cmp(second),
+        (JsonValue::Num(JsonNum::Int(first)), JsonValue::Num(JsonNum::Int(second))) => {
+            first.cmp(second)
+        }
+        (JsonValue::Num(_), JsonValue::Num(_)) => {
+            let first = first.as_f64().unwrap();
+            first.total_cmp(&second.as_f64().unwrap())
+        }
+        (JsonValue::String(first), JsonValue::String(second)) => first.cmp(second),
+        (JsonValue::Vec(first), JsonValue::Vec(second)) => first
+            .iter()
+            .zip(second.iter())
+            .map(|(first, second)| compare(first, second))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or_else(|| first.len().cmp(&second.len())),
+        _ => type_rank(first).cmp(&type_rank(second)),
+    }
+}
+
+impl Deref for JsonArray {
+    type Target = [JsonValue];
+
+    fn deref(&self) -> &[JsonValue] {
+        &self.elements
+    }
+}
+
+impl DerefMut for JsonArray {
+    fn deref_mut(&mut self) -> &mut [JsonValue] {
+        &mut self.elements
+    }
+}
+
+impl From<Vec<JsonValue>> for JsonArray {
+    fn from(elements: Vec<JsonValue>) -> JsonArray {
+        JsonArray { elements }
+    }
+}
+
+impl<T: Into<JsonValue>> Extend<T> for JsonArray {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        self.elements.extend(values.into_iter().map(Into::into));
+    }
+}
+
+impl<T: Into<JsonValue>> FromIterator<T> for JsonArray {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> JsonArray {
+        let mut array = JsonArray::new();
+        array.extend(values);
+        array
+    }
+}
+
+impl IntoIterator for JsonArray {
+    type Item = JsonValue;
+    type IntoIter = vec::IntoIter<JsonValue>;
+
+    fn into_iter(self) -> vec::IntoIter<JsonValue> {
+        self.elements.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a JsonArray {
+    type Item = &'a JsonValue;
+    type IntoIter = slice::Iter<'a, JsonValue>;
+
+    fn into_iter(self) -> slice::Iter<'a, JsonValue> {
+        self.elements.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut JsonArray {
+    type Item = &'a mut JsonValue;
+    type IntoIter = slice::IterMut<'a, JsonValue>;
+
+    fn into_iter(self) -> slice::IterMut<'a, JsonValue> {
+        self.elements.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_array(json: &str) -> JsonArray {
+        match crate::parse(format!("{{\"array\": {}}}", json)).remove("array") {
+            Some(JsonValue::Vec(array)) => array,
+            other => panic!("Expected array, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn collection_operations() {
+        let mut array: JsonArray = vec!["b", "a"].into_iter().collect();
+        array.push(3);
+        array.insert(0, true);
+        array.extend(vec![JsonValue::Null, JsonValue::Null]);
+        assert_eq!(array.len(), 6);
+        assert_eq!(array.remove(6), None);
+        assert_eq!(array.remove(5), Some(JsonValue::Null));
+        array.truncate(4);
+        array[1..3].sort_by(compare);
+
+        assert_eq!(
+            JsonValue::Vec(array.clone()).to_string_compact().unwrap(),
+            "[true,\"a\",\"b\",3]"
+        );
+        assert_eq!(
+            array.find(|value| value.as_i64().is_some()),
+            Some(&JsonValue::Num(JsonNum::Int(3)))
+        );
+    }
+
+    #[test]
+    fn dedup_and_sort_by_pointer() {
+        let mut array = parse_array("[1, 1, {\"a\": 1, \"b\": 2}, {\"b\": 2, \"a\": 1}, 1]");
+        array.dedup();
+        assert_eq!(
+            JsonValue::Vec(array).to_string_compact().unwrap(),
+            "[1,{\"a\":1,\"b\":2},1]"
+        );
+
+        let mut array = parse_array(
+            "[{\"n\": 2, \"x\": 1}, {\"n\": 1.5}, {\"x\": 1, \"n\": 2}, {}, {\"n\": \"z\"}, {\"n\": 1.5}]",
+        );
+        array.dedup_all();
+        assert!(matches!(
+            array.sort_by_pointer("n"),
+            Err(Error::InvalidPointer(pointer)) if pointer == "n"
+        ));
+        array.sort_by_pointer("/n").unwrap();
+        assert_eq!(
+            JsonValue::Vec(array).to_string_compact().unwrap(),
+            "[{},{\"n\":1.5},{\"n\":2,\"x\":1},{\"n\":\"z\"}]"
+        );
+    }
+
+    #[test]
+    fn typed_iteration() {
+        let array = parse_array("[\"a\", \"b\", 3]");
+        assert_eq!(
+            array.iter_str().take(2).collect::<Result<Vec<_>, _>>(),
+            Ok(vec!["a", "b"])
+        );
+        let err = array.iter_str().collect::<Result<Vec<_>, _>>().unwrap_err();
+        assert_eq!(err.key, "2");
+        assert_eq!(err.actual, "integer");
+        let err = parse_array("[1, -2]")
+            .iter_u64()
+            .nth(1)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.actual, "negative integer");
+        assert!(parse_array("[1, 2]").iter_u64().all(|int| int.is_ok()));
+    }
+}
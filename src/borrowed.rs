@@ -0,0 +1,175 @@
+//! A DOM borrowing its strings and keys from the input, which only
+//! allocates for strings that contain escapes that have to be decoded.
+
+use std::borrow::Cow;
+
+use super::error::Error;
+use super::lazy::{LazyDocument, LazyKind, LazyValue};
+use super::scan::{parse_number, unescape};
+use super::types::{JsonNum, JsonObject, JsonValue};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BorrowedValue<'a> {
+    String(Cow<'a, str>),
+    Num(JsonNum),
+    Bool(bool),
+    Null,
+    Vec(Vec<BorrowedValue<'a>>),
+    /// Members in the order they appear in the input.
+    Object(Vec<(Cow<'a, str>, BorrowedValue<'a>)>),
+}
+
+impl<'a> BorrowedValue<'a> {
+    /// The value of `key` if this is an object that has it. If the key is
+    /// repeated, the last value counts, as it does for `into_owned` and
+    /// `rson::parse`.
+    pub fn get_value(&self, key: &str) -> Option<&BorrowedValue<'a>> {
+        match self {
+            BorrowedValue::Object(members) => members
+                .iter()
+                .rev()
+                .find(|(member, _)| member == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Copies everything still borrowed from the input into an owned value.
+    pub fn into_owned(self) -> JsonValue {
+        match self {
+            BorrowedValue::String(string) => JsonValue::String(string.into_owned()),
+            BorrowedValue::Num(num) => JsonValue::Num(num),
+            BorrowedValue::Bool(bool) => JsonValue::Bool(bool),
+            BorrowedValue::Null => JsonValue::Null,
+            BorrowedValue::Vec(vec) => {
+                JsonValue::Vec(vec.into_iter().map(BorrowedValue::into_owned).collect())
+            }
+            BorrowedValue::Object(members) => {
+                let mut object = JsonObject::new();
+                for (key, value) in members {
+                    object.insert(key.into_owned(), value.into_owned());
+                }
+                JsonValue::Object(object)
+            }
+        }
+    }
+}
+
+/// Parses a JSON document of any type of value, borrowing from `json`.
+///
+/// The document is checked by the same skimming pass as `LazyDocument`,
+/// which also finds where every value is, so this only has to copy the
+/// shape of the tree and decode its strings and numbers.
+pub fn parse_borrowed(json: &str) -> Result<BorrowedValue<'_>, Error> {
+    let document = LazyDocument::new(json)?;
+    let root = document.root();
+    borrow(json, &root)
+}
+
+fn borrow<'a>(json: &'a str, value: &LazyValue<'_>) -> Result<BorrowedValue<'a>, Error> {
+    let raw = &json[value.range()];
+    let value = match value.kind() {
+        LazyKind::String => BorrowedValue::String(unquote(raw)),
+        LazyKind::Number => match parse_number(raw) {
+            Some(num) => BorrowedValue::Num(num),
+            None => {
+                let message = format!("Number does not fit an i64: {}", raw);
+                return Err(Error::syntax(message, value.range().start as u64));
+            }
+        },
+        LazyKind::Bool => BorrowedValue::Bool(raw == "true"),
+        LazyKind::Null => BorrowedValue::Null,
+        LazyKind::Array => BorrowedValue::Vec(
+            value
+                .iter()
+                .map(|element| borrow(json, &element))
+                .collect::<Result<_, _>>()?,
+        ),
+        LazyKind::Object => BorrowedValue::Object(
+            value
+                .members()
+                .map(|(key, value)| Ok((unquote(&json[key.range()]), borrow(json, &value)?)))
+                .collect::<Result<_, Error>>()?,
+        ),
+    };
+    Ok(value)
+}
+
+/// Decodes a string whose escapes were checked when it was skimmed.
+fn unquote(raw: &str) -> Cow<'_, str> {
+    unescape(&raw[1..raw.len() - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_files;
+
+    #[test]
+    fn borrows_unless_escaped() {
+        let json = "{\"plain\": \"text\", \"esc\\u0061ped\": \"line\\nbreak\"}";
+        let value = parse_borrowed(json).unwrap();
+
+        let members = match &value {
+            BorrowedValue::Object(members) => members,
+            other => panic!("Expected object, got {:?}", other),
+        };
+        assert!(matches!(members[0].0, Cow::Borrowed("plain")));
+        assert!(matches!(
+            members[0].1,
+            BorrowedValue::String(Cow::Borrowed("text"))
+        ));
+        assert!(matches!(&members[1].0, Cow::Owned(key) if key == "escaped"));
+        assert_eq!(
+            value.get_value("escaped"),
+            Some(&BorrowedValue::String(Cow::Owned(
+                "line\nbreak".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn into_owned_matches_parse() {
+        for (file, json) in test_files() {
+            let value = parse_borrowed(&json).unwrap().into_owned();
+            assert_eq!(
+                value,
+                JsonValue::Object(crate::parse(json.clone())),
+                "{}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn last_duplicate_key_wins() {
+        let value = parse_borrowed("{\"a\": 1, \"a\": 2}").unwrap();
+        assert_eq!(
+            value.get_value("a"),
+            Some(&BorrowedValue::Num(JsonNum::Int(2)))
+        );
+        let expected = crate::parse("{\"a\": 2}".to_string());
+        assert_eq!(value.into_owned(), JsonValue::Object(expected));
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let cases = [
+            ("[1, 2", 5),
+            ("{\"a\" 1}", 5),
+            ("[tru]", 1),
+            ("\"open", 5),
+            ("1 2", 2),
+            ("[\u{c}1]", 1),
+            ("[01]", 1),
+            ("[1.]", 1),
+            ("[1, 99999999999999999999]", 4),
+        ];
+        for (json, expected) in cases.iter() {
+            match parse_borrowed(json) {
+                Err(Error::Syntax { offset, .. }) => assert_eq!(offset, *expected, "{}", json),
+                other => panic!("expected syntax error for {}, got {:?}", json, other),
+            }
+        }
+    }
+}
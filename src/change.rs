@@ -0,0 +1,263 @@
+//! Edits of a `JsonObject` at JSON Pointer paths, described as `Change`s
+//! that can be applied, inverted and reported.
+
+use std::ops::Deref;
+
+use super::error::Error;
+use super::pointer;
+use super::types::{JsonArray, JsonObject, JsonValue};
+
+/// One edit at a JSON Pointer path, with enough information to undo it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// A key was added at `index` among the members of an object, or an
+    /// element was inserted at `index` of an array.
+    Insert {
+        path: String,
+        index: usize,
+        value: JsonValue,
+    },
+    /// The key or element at `index` was removed.
+    Remove {
+        path: String,
+        index: usize,
+        value: JsonValue,
+    },
+    Replace {
+        path: String,
+        old: JsonValue,
+        new: JsonValue,
+    },
+}
+
+impl Change {
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Insert { path, .. }
+            | Change::Remove { path, .. }
+            | Change::Replace { path, .. } => path,
+        }
+    }
+
+    /// The value at the path before the change, if there was one.
+    pub fn old_value(&self) -> Option<&JsonValue> {
+        match self {
+            Change::Insert { .. } => None,
+            Change::Remove { value, .. } => Some(value),
+            Change::Replace { old, .. } => Some(old),
+        }
+    }
+
+    /// The value at the path after the change, if there is one.
+    pub fn new_value(&self) -> Option<&JsonValue> {
+        match self {
+            Change::Insert { value, .. } => Some(value),
+            Change::Remove { .. } => None,
+            Change::Replace { new, .. } => Some(new),
+        }
+    }
+
+    /// The change that undoes this one.
+    pub fn inverse(&self) -> Change {
+        match self.clone() {
+            Change::Insert { path, index, value } => Change::Remove { path, index, value },
+            Change::Remove { path, index, value } => Change::Insert { path, index, value },
+            Change::Replace { path, old, new } => Change::Replace {
+                path,
+                old: new,
+                new: old,
+            },
+        }
+    }
+}
+
+/// The array or object an edit is made in.
+pub(crate) enum Container<'a> {
+    Object(&'a mut JsonObject),
+    Vec(&'a mut JsonArray),
+}
+
+/// Splits `path` into the container holding its target and the last token.
+pub(crate) fn locate<'a>(
+    root: &'a mut JsonObject,
+    path: &str,
+) -> Result<(Container<'a>, String), Error> {
+    let mut tokens = split(path)?;
+    let last = tokens.pop().ok_or(Error::EmptyPath)?;
+
+    let mut container = Container::Object(root);
+    for token in &tokens {
+        let child = match container {
+            Container::Object(object) => object.get_mut(token),
+            Container::Vec(vec) => {
+                let index = parse_index(token, path)?;
+                vec.get_mut(index)
+            }
+        };
+        container = match child {
+            Some(JsonValue::Object(object)) => Container::Object(object),
+            Some(JsonValue::Vec(vec)) => Container::Vec(vec),
+            Some(_) => return Err(Error::NotAContainer(path.to_string())),
+            None => return Err(Error::NotFound(path.to_string())),
+        };
+    }
+    Ok((container, last))
+}
+
+/// Like `pointer::split`, with the error an edit reports.
+pub(crate) fn split(path: &str) -> Result<Vec<String>, Error> {
+    pointer::split(path).map_err(|_| Error::InvalidPointer(path.to_string()))
+}
+
+pub(crate) fn parse_index(token: &str, path: &str) -> Result<usize, Error> {
+    token
+        .parse::<usize>()
+        .map_err(|_| Error::InvalidIndex(path.to_string()))
+}
+
+/// Works out the change that sets the value at `path`: replacing what is
+/// there, adding a key, or appending to an array for `-` or the length.
+pub(crate) fn plan_set(
+    root: &mut JsonObject,
+    path: &str,
+    value: JsonValue,
+) -> Result<Change, Error> {
+    let path = path.to_string();
+    match locate(root, &path)? {
+        (Container::Object(object), key) => Ok(match object.get_value(&key) {
+            Some(old) => Change::Replace {
+                old: old.clone(),
+                new: value,
+                path,
+            },
+            None => Change::Insert {
+                index: object.len(),
+                value,
+                path,
+            },
+        }),
+        (Container::Vec(vec), token) => {
+            let index = match token.as_str() {
+                "-" => vec.len(),
+                token => parse_index(token, &path)?,
+            };
+            if index < vec.len() {
+                Ok(Change::Replace {
+                    old: vec[index].clone(),
+                    new: value,
+                    path,
+                })
+            } else if index == vec.len() {
+                Ok(Change::Insert {
+                    path: format!("{}/{}", &path[..path.rfind('/').unwrap()], index),
+                    index,
+                    value,
+                })
+            } else {
+                Err(Error::InvalidIndex(path))
+            }
+        }
+    }
+}
+
+/// Works out the change that inserts `value` at an array index, moving the
+/// elements from there on back.
+pub(crate) fn plan_insert_element(
+    root: &mut JsonObject,
+    path: &str,
+    value: JsonValue,
+) -> Result<Change, Error> {
+    match locate(root, path)? {
+        (Container::Vec(vec), token) => {
+            let index = parse_index(&token, path)?;
+            if index > vec.len() {
+                return Err(Error::InvalidIndex(path.to_string()));
+            }
+            Ok(Change::Insert {
+                path: path.to_string(),
+                index,
+                value,
+            })
+        }
+        (Container::Object(_), _) => Err(Error::InvalidIndex(path.to_string())),
+    }
+}
+
+/// Works out the change that removes the key or element at `path`.
+pub(crate) fn plan_remove(root: &mut JsonObject, path: &str) -> Result<Change, Error> {
+    let (index, value) = match locate(root, path)? {
+        (Container::Object(object), key) => match object.position(&key) {
+            Some(index) => (index, object.get_value(&key).unwrap().clone()),
+            None => return Err(Error::NotFound(path.to_string())),
+        },
+        (Container::Vec(vec), token) => {
+            let index = parse_index(&token, path)?;
+            match vec.get(index) {
+                Some(value) => (index, value.clone()),
+                None => return Err(Error::NotFound(path.to_string())),
+            }
+        }
+    };
+    Ok(Change::Remove {
+        path: path.to_string(),
+        index,
+        value,
+    })
+}
+
+/// Makes `change` to `root`, which has to be in the state it describes:
+/// a removed or replaced value has to be the one recorded, and a removed
+/// key has to be at the recorded position. Fails without changing
+/// anything if it isn't.
+pub(crate) fn apply(root: &mut JsonObject, change: &Change) -> Result<(), Error> {
+    let (container, last) = locate(root, change.path())?;
+    let path = change.path();
+    match (change, container) {
+        (Change::Insert { index, value, .. }, Container::Object(object)) => {
+            if *index > object.len() {
+                return Err(Error::InvalidIndex(path.to_string()));
+            }
+            if !object.insert_at(*index, last, value.clone()) {
+                return Err(Error::KeyExists(path.to_string()));
+            }
+        }
+        (Change::Insert { index, value, .. }, Container::Vec(vec)) => {
+            if *index > vec.len() {
+                return Err(Error::InvalidIndex(path.to_string()));
+            }
+            vec.insert(*index, value.clone())
+        }
+        (Change::Remove { index, value, .. }, Container::Object(object)) => {
+            check(path, object.get_value(&last), value)?;
+            if object.position(&last) != Some(*index) {
+                return Err(Error::Conflict(path.to_string()));
+            }
+            object.remove(&last);
+        }
+        (Change::Remove { index, value, .. }, Container::Vec(vec)) => {
+            check(path, vec.get(*index), value)?;
+            vec.remove(*index);
+        }
+        (Change::Replace { old, new, .. }, Container::Object(object)) => {
+            *check(path, object.get_mut(&last), old)? = new.clone();
+        }
+        (Change::Replace { old, new, .. }, Container::Vec(vec)) => {
+            let index = parse_index(&last, path)?;
+            *check(path, vec.get_mut(index), old)? = new.clone();
+        }
+    }
+    Ok(())
+}
+
+/// Checks that `current`, the value at `path`, is the `expected` one a
+/// change was made from, and passes it on.
+fn check<T>(path: &str, current: Option<T>, expected: &JsonValue) -> Result<T, Error>
+where
+    T: Deref<Target = JsonValue>,
+{
+    match current {
+        None => Err(Error::NotFound(path.to_string())),
+        Some(current) if *current != *expected => Err(Error::Conflict(path.to_string())),
+        Some(current) => Ok(current),
+    }
+}
@@ -0,0 +1,163 @@
+//! ANSI colored output for reading JSON in a terminal.
+
+use std::env;
+use std::ffi::OsStr;
+
+use super::error::Error;
+use super::serializer::{Pretty, PrettyConfig};
+use super::types::JsonValue;
+
+/// The kinds of token that can be given their own color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Role {
+    Key,
+    String,
+    Number,
+    Bool,
+    Null,
+    Punctuation,
+}
+
+/// The SGR parameters used for each kind of token, e.g. `"32"` for green or
+/// `"1;34"` for bold blue. An empty string leaves that kind uncolored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub key: String,
+    pub string: String,
+    pub number: String,
+    pub bool: String,
+    pub null: String,
+    pub punctuation: String,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            key: "1;34".to_string(),
+            string: "32".to_string(),
+            number: "33".to_string(),
+            bool: "35".to_string(),
+            null: "2".to_string(),
+            punctuation: String::new(),
+        }
+    }
+}
+
+impl Theme {
+    /// A theme without any colors, which renders just like the pretty printer.
+    pub fn plain() -> Theme {
+        Theme {
+            key: String::new(),
+            string: String::new(),
+            number: String::new(),
+            bool: String::new(),
+            null: String::new(),
+            punctuation: String::new(),
+        }
+    }
+
+    /// The default theme, or the plain one if the `NO_COLOR` environment
+    /// variable is set to anything but an empty string.
+    pub fn from_env() -> Theme {
+        Theme::for_no_color(env::var_os("NO_COLOR").as_deref())
+    }
+
+    fn for_no_color(no_color: Option<&OsStr>) -> Theme {
+        match no_color {
+            Some(value) if !value.is_empty() => Theme::plain(),
+            _ => Theme::default(),
+        }
+    }
+
+    pub(crate) fn code(&self, role: Role) -> &str {
+        match role {
+            Role::Key => &self.key,
+            Role::String => &self.string,
+            Role::Number => &self.number,
+            Role::Bool => &self.bool,
+            Role::Null => &self.null,
+            Role::Punctuation => &self.punctuation,
+        }
+    }
+}
+
+/// Lays `value` out like `to_string_pretty` and colors it with `theme`.
+/// Use `Theme::from_env` to respect `NO_COLOR`.
+pub fn to_string_colored(
+    value: &JsonValue,
+    config: &PrettyConfig,
+    theme: &Theme,
+) -> Result<String, Error> {
+    let mut out = String::new();
+    Pretty {
+        config,
+        theme: Some(theme),
+    }
+    .write_document(&mut out, value)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::to_string_pretty;
+    use std::fs;
+
+    fn strip_colors(colored: &str) -> String {
+        let mut out = String::new();
+        let mut chars = colored.chars();
+        while let Some(char) = chars.next() {
+            if char == '\x1b' {
+                chars.by_ref().find(|&char| char == 'm');
+            } else {
+                out.push(char);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn colors_each_kind_of_token() {
+        let value = JsonValue::Object(crate::parse(
+            "{\"list\": [\"a\", 1, true, null]}".to_string(),
+        ));
+        let theme = Theme {
+            punctuation: "37".to_string(),
+            ..Theme::default()
+        };
+        let colored = to_string_colored(&value, &PrettyConfig::default(), &theme).unwrap();
+
+        assert!(colored.contains("\x1b[1;34m\"list\"\x1b[0m\x1b[37m:\x1b[0m"));
+        assert!(colored.contains("\x1b[32m\"a\"\x1b[0m"));
+        assert!(colored.contains("\x1b[33m1\x1b[0m"));
+        assert!(colored.contains("\x1b[35mtrue\x1b[0m"));
+        assert!(colored.contains("\x1b[2mnull\x1b[0m"));
+    }
+
+    #[test]
+    fn same_layout_as_pretty_printer() {
+        let json = fs::read_to_string("./test_files/test_package.json").unwrap();
+        let value = JsonValue::Object(crate::parse(json));
+        let config = PrettyConfig {
+            max_width: Some(60),
+            ..PrettyConfig::default()
+        };
+
+        let colored = to_string_colored(&value, &config, &Theme::default()).unwrap();
+        assert_eq!(
+            strip_colors(&colored),
+            to_string_pretty(&value, &config).unwrap()
+        );
+        assert_eq!(
+            to_string_colored(&value, &config, &Theme::plain()).unwrap(),
+            to_string_pretty(&value, &config).unwrap()
+        );
+    }
+
+    #[test]
+    fn no_color_disables_colors() {
+        assert_eq!(Theme::for_no_color(Some(OsStr::new("1"))), Theme::plain());
+        assert_eq!(Theme::for_no_color(Some(OsStr::new(""))), Theme::default());
+        assert_eq!(Theme::for_no_color(None), Theme::default());
+    }
+}
@@ -0,0 +1,552 @@
+//! An arena representation of a parsed document. Nodes, element and member
+//! lists, and the text of strings and keys are bump allocated into a few
+//! arenas and addressed by `NodeId`s, so building a document makes
+//! O(log n) allocations instead of one per node, and dropping it frees
+//! those chunks without visiting any node.
+
+use std::io::Read;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::error::Error;
+use super::lexer::{Delimiters, JsonTokenType, Numbers, Token, TokenReader};
+use super::types::{JsonNum, JsonObject, JsonValue, SMALL_OBJECT_LEN};
+
+/// The number of values that fit in the first chunk of an arena. Every
+/// chunk after it is at least twice as large as the one before.
+const FIRST_CHUNK: usize = 64;
+
+/// Tells the ids of one document from those of another.
+static NEXT_DOCUMENT: AtomicUsize = AtomicUsize::new(0);
+
+/// Where a run of values was put in an arena.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Slot {
+    chunk: usize,
+    start: usize,
+    end: usize,
+}
+
+impl Slot {
+    fn len(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+/// Where a node was put in `Document::nodes`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Address {
+    chunk: usize,
+    index: usize,
+}
+
+/// The capacity of the chunk to start when `len` more values don't fit in
+/// a chunk of `last` capacity.
+fn next_capacity(last: Option<usize>, len: usize) -> usize {
+    last.map_or(FIRST_CHUNK, |last| last * 2).max(len)
+}
+
+/// A bump allocator. Values are appended to the last chunk, which is never
+/// grown, so they never move. Every value is `Copy`, so dropping the arena
+/// runs no destructors.
+#[derive(Debug)]
+struct Arena<T> {
+    chunks: Vec<Vec<T>>,
+}
+
+impl<T: Copy> Arena<T> {
+    fn new() -> Arena<T> {
+        Arena { chunks: vec![] }
+    }
+
+    /// Puts `values` next to each other.
+    fn alloc(&mut self, values: &[T]) -> Slot {
+        let fits = self.chunks.last().map_or(false, |chunk| {
+            chunk.capacity() - chunk.len() >= values.len()
+        });
+        if !fits {
+            let capacity = next_capacity(self.chunks.last().map(Vec::capacity), values.len());
+            self.chunks.push(Vec::with_capacity(capacity));
+        }
+
+        let chunk = self.chunks.len() - 1;
+        let values_in_chunk = &mut self.chunks[chunk];
+        let start = values_in_chunk.len();
+        values_in_chunk.extend_from_slice(values);
+        Slot {
+            chunk,
+            start,
+            end: values_in_chunk.len(),
+        }
+    }
+
+    fn get(&self, slot: Slot) -> &[T] {
+        &self.chunks[slot.chunk][slot.start..slot.end]
+    }
+
+    fn len(&self) -> usize {
+        self.chunks.iter().map(Vec::len).sum()
+    }
+}
+
+/// The arena holding the text of strings and keys.
+#[derive(Debug)]
+struct Text {
+    chunks: Vec<String>,
+}
+
+impl Text {
+    fn alloc(&mut self, string: &str) -> Slot {
+        let fits = self.chunks.last().map_or(false, |chunk| {
+            chunk.capacity() - chunk.len() >= string.len()
+        });
+        if !fits {
+            let capacity = next_capacity(self.chunks.last().map(String::capacity), string.len());
+            self.chunks.push(String::with_capacity(capacity));
+        }
+
+        let chunk = self.chunks.len() - 1;
+        let text = &mut self.chunks[chunk];
+        let start = text.len();
+        text.push_str(string);
+        Slot {
+            chunk,
+            start,
+            end: text.len(),
+        }
+    }
+
+    fn get(&self, slot: Slot) -> &str {
+        &self.chunks[slot.chunk][slot.start..slot.end]
+    }
+}
+
+/// A node of the `Document` it came from. Using it with any other document
+/// panics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    document: usize,
+    address: Address,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Node {
+    /// Text in `Document::text`.
+    String(Slot),
+    Num(JsonNum),
+    Bool(bool),
+    Null,
+    /// Elements in `Document::elements`.
+    Vec(Slot),
+    /// Members in `Document::members`, in source order, and for objects
+    /// with more than `SMALL_OBJECT_LEN` members the same members again,
+    /// sorted by key, to look keys up by binary search.
+    Object {
+        members: Slot,
+        sorted: Option<Slot>,
+    },
+}
+
+#[derive(Debug)]
+pub struct Document {
+    id: usize,
+    nodes: Arena<Node>,
+    text: Text,
+    elements: Arena<Address>,
+    /// Keys, as slots of `text`, with their values.
+    members: Arena<(Slot, Address)>,
+    root: Address,
+}
+
+impl Document {
+    /// Builds the node tree of `json`, whose root may be any value, not only
+    /// an object.
+    pub fn parse(json: &str) -> Result<Document, Error> {
+        Document::from_reader(json.as_bytes())
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> Result<Document, Error> {
+        let mut builder = Builder {
+            tokens: TokenReader::new(reader),
+            document: Document {
+                id: NEXT_DOCUMENT.fetch_add(1, Ordering::Relaxed),
+                nodes: Arena::new(),
+                text: Text { chunks: vec![] },
+                elements: Arena::new(),
+                members: Arena::new(),
+                root: Address { chunk: 0, index: 0 },
+            },
+            pending_elements: vec![],
+            pending_members: vec![],
+        };
+
+        let first = builder.tokens.expect_token()?;
+        let root = builder.value(first)?;
+        if let Some((token, range)) = builder.tokens.next_token()? {
+            let message = format!("Unexpected token after end of document: {:?}", token);
+            return Err(Error::syntax(message, range.start));
+        }
+
+        builder.document.root = root;
+        Ok(builder.document)
+    }
+
+    pub fn root(&self) -> NodeRef<'_> {
+        self.at(self.root)
+    }
+
+    /// Panics if `id` is not from this document.
+    pub fn node(&self, id: NodeId) -> NodeRef<'_> {
+        assert_eq!(id.document, self.id, "Node id from another document");
+        self.at(id.address)
+    }
+
+    /// The number of nodes, i.e. of values at any depth.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn at(&self, address: Address) -> NodeRef<'_> {
+        NodeRef {
+            document: self,
+            address,
+        }
+    }
+}
+
+/// A node of a `Document`, with accessors like those of `JsonValue`.
+#[derive(Clone, Copy, Debug)]
+pub struct NodeRef<'a> {
+    document: &'a Document,
+    address: Address,
+}
+
+impl<'a> NodeRef<'a> {
+    pub fn id(&self) -> NodeId {
+        NodeId {
+            document: self.document.id,
+            address: self.address,
+        }
+    }
+
+    fn node(&self) -> Node {
+        self.document.nodes.chunks[self.address.chunk][self.address.index]
+    }
+
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self.node() {
+            Node::String(text) => Some(self.document.text.get(text)),
+            _ => None,
+        }
+    }
+
+    pub fn as_num(&self) -> Option<JsonNum> {
+        match self.node() {
+            Node::Num(num) => Some(num),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.node() {
+            Node::Bool(bool) => Some(bool),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self.node(), Node::Null)
+    }
+
+    pub fn is_vec(&self) -> bool {
+        matches!(self.node(), Node::Vec(_))
+    }
+
+    pub fn is_object(&self) -> bool {
+        matches!(self.node(), Node::Object { .. })
+    }
+
+    /// The number of elements or members, or 0 for anything else.
+    pub fn len(&self) -> usize {
+        match self.node() {
+            Node::Vec(elements) => elements.len(),
+            Node::Object { members, .. } => members.len(),
+            _ => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The element at `index` of an array.
+    pub fn get(&self, index: usize) -> Option<NodeRef<'a>> {
+        match self.node() {
+            Node::Vec(elements) => {
+                let address = *self.document.elements.get(elements).get(index)?;
+                Some(self.document.at(address))
+            }
+            _ => None,
+        }
+    }
+
+    /// The value of `key` in an object. If the key appears more than once,
+    /// the last value is returned, as `to_value` and `parse` keep it.
+    pub fn get_value(&self, key: &str) -> Option<NodeRef<'a>> {
+        let document = self.document;
+        let is_key = |member: &&(Slot, Address)| document.text.get(member.0) == key;
+        let found = match self.node() {
+            Node::Object {
+                sorted: Some(sorted),
+                ..
+            } => {
+                let sorted = document.members.get(sorted);
+                let end = sorted.partition_point(|member| document.text.get(member.0) <= key);
+                sorted[..end].last().filter(is_key)
+            }
+            Node::Object { members, .. } => document.members.get(members).iter().rev().find(is_key),
+            _ => None,
+        };
+        found.map(|&(_, address)| document.at(address))
+    }
+
+    pub fn get_keys(&self) -> Vec<&'a str> {
+        self.to_iter().map(|(key, _)| key).collect()
+    }
+
+    /// Handles to the elements of an array node. Other nodes have none.
+    pub fn elements(&self) -> impl Iterator<Item = NodeRef<'a>> + 'a {
+        let document = self.document;
+        let addresses = match self.node() {
+            Node::Vec(elements) => document.elements.get(elements),
+            _ => &[],
+        };
+        addresses.iter().map(move |&address| document.at(address))
+    }
+
+    /// The keys of an object node, borrowed from the document, with handles
+    /// to their values in source order. Other nodes have none.
+    pub fn to_iter(&self) -> impl Iterator<Item = (&'a str, NodeRef<'a>)> + 'a {
+        let document = self.document;
+        let members = match self.node() {
+            Node::Object { members, .. } => document.members.get(members),
+            _ => &[],
+        };
+        members
+            .iter()
+            .map(move |&(key, address)| (document.text.get(key), document.at(address)))
+    }
+
+    /// Copies this node and everything below it out of the arena.
+    pub fn to_value(&self) -> JsonValue {
+        match self.node() {
+            Node::String(text) => JsonValue::String(self.document.text.get(text).to_string()),
+            Node::Num(num) => JsonValue::Num(num),
+            Node::Bool(bool) => JsonValue::Bool(bool),
+            Node::Null => JsonValue::Null,
+            Node::Vec(_) => JsonValue::Vec(self.elements().map(|node| node.to_value()).collect()),
+            Node::Object { .. } => {
+                let mut object = JsonObject::new();
+                for (key, value) in self.to_iter() {
+                    object.insert(key.to_string(), value.to_value());
+                }
+                JsonValue::Object(object)
+            }
+        }
+    }
+}
+
+struct Builder<R: Read> {
+    tokens: TokenReader<R>,
+    document: Document,
+    /// The elements of the arrays being built, innermost last. They are
+    /// moved to `Document::elements` once an array is complete, so that
+    /// the elements of every array end up next to each other.
+    pending_elements: Vec<Address>,
+    pending_members: Vec<(Slot, Address)>,
+}
+
+impl<R: Read> Builder<R> {
+    fn value(&mut self, first: (Token, Range<u64>)) -> Result<Address, Error> {
+        let (token, range) = first;
+        let node = match token.get_token() {
+            JsonTokenType::Delimiter(Delimiters::LeftBracket) => self.vec()?,
+            JsonTokenType::Delimiter(Delimiters::LeftBrace) => self.object()?,
+            JsonTokenType::String(string) => Node::String(self.document.text.alloc(&string)),
+            JsonTokenType::Number(Numbers::Integer(int)) => Node::Num(JsonNum::Int(int)),
+            JsonTokenType::Number(Numbers::Float(float)) => Node::Num(JsonNum::Float(float)),
+            JsonTokenType::Boolean(bool) => Node::Bool(bool),
+            JsonTokenType::Null => Node::Null,
+            JsonTokenType::Delimiter(del) => {
+                let message = format!("Unexpected token: {:?}", del);
+                return Err(Error::syntax(message, range.start));
+            }
+        };
+        let slot = self.document.nodes.alloc(&[node]);
+        Ok(Address {
+            chunk: slot.chunk,
+            index: slot.start,
+        })
+    }
+
+    fn vec(&mut self) -> Result<Node, Error> {
+        let mark = self.pending_elements.len();
+        let mut next = self.tokens.expect_token()?;
+        if next.0.get_token() != JsonTokenType::Delimiter(Delimiters::RightBracket) {
+            loop {
+                let element = self.value(next)?;
+                self.pending_elements.push(element);
+                let del = self
+                    .tokens
+                    .expect_delimiter(&[Delimiters::Comma, Delimiters::RightBracket])?;
+                if del == Delimiters::RightBracket {
+                    break;
+                }
+                next = self.tokens.expect_token()?;
+            }
+        }
+
+        let elements = self.document.elements.alloc(&self.pending_elements[mark..]);
+        self.pending_elements.truncate(mark);
+        Ok(Node::Vec(elements))
+    }
+
+    fn object(&mut self) -> Result<Node, Error> {
+        let mark = self.pending_members.len();
+        let mut first_member = true;
+        loop {
+            let (token, range) = self.tokens.expect_token()?;
+            let key = match token.get_token() {
+                JsonTokenType::Delimiter(Delimiters::RightBrace) if first_member => break,
+                JsonTokenType::String(key) => key,
+                unexpected => {
+                    let message =
+                        format!("Expected key to be string, instead got: {:?}", unexpected);
+                    return Err(Error::syntax(message, range.start));
+                }
+            };
+            first_member = false;
+
+            let key = self.document.text.alloc(&key);
+            self.tokens.expect_delimiter(&[Delimiters::Colon])?;
+            let next = self.tokens.expect_token()?;
+            let value = self.value(next)?;
+            self.pending_members.push((key, value));
+
+            if self
+                .tokens
+                .expect_delimiter(&[Delimiters::Comma, Delimiters::RightBrace])?
+                == Delimiters::RightBrace
+            {
+                break;
+            }
+        }
+
+        let members = self.document.members.alloc(&self.pending_members[mark..]);
+        let sorted = if members.len() > SMALL_OBJECT_LEN {
+            // a stable sort keeps duplicate keys in source order, so the
+            // last of them is still found last
+            let text = &self.document.text;
+            self.pending_members[mark..].sort_by(|a, b| text.get(a.0).cmp(text.get(b.0)));
+            Some(self.document.members.alloc(&self.pending_members[mark..]))
+        } else {
+            None
+        };
+        self.pending_members.truncate(mark);
+        Ok(Node::Object { members, sorted })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_files;
+
+    #[test]
+    fn navigate_document() {
+        let json = "{\"name\": \"rson\", \"list\": [1, 2.5, [true, null]], \"empty\": {}}";
+        let document = Document::parse(json).unwrap();
+        let root = document.root();
+
+        assert!(root.is_object());
+        assert_eq!(root.get_keys(), vec!["name", "list", "empty"]);
+        assert_eq!(root.get_value("name").unwrap().as_str(), Some("rson"));
+
+        let list = root.get_value("list").unwrap();
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.get(0).unwrap().as_num(), Some(JsonNum::Int(1)));
+        assert_eq!(list.get(1).unwrap().as_num(), Some(JsonNum::Float(2.5)));
+        let nested = list.get(2).unwrap();
+        assert_eq!(nested.get(0).unwrap().as_bool(), Some(true));
+        assert!(nested.get(1).unwrap().is_null());
+        assert!(list.get(3).is_none());
+
+        assert!(root.get_value("empty").unwrap().is_empty());
+        assert_eq!(document.node(nested.id()).len(), 2);
+    }
+
+    #[test]
+    fn same_values_as_parse() {
+        for (file, json) in test_files() {
+            let document = Document::parse(&json).unwrap();
+            assert_eq!(
+                document.root().to_value(),
+                JsonValue::Object(crate::parse(json)),
+                "{}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn finds_keys_of_large_objects() {
+        let members: Vec<String> = (0..100)
+            .map(|i| format!("\"key_{}\": {}", i % 60, i))
+            .collect();
+        let json = format!("{{{}}}", members.join(", "));
+        let document = Document::parse(&json).unwrap();
+        let root = document.root();
+
+        assert_eq!(root.len(), 100);
+        assert_eq!(root.get_keys()[..3], ["key_0", "key_1", "key_2"]);
+        for i in 0..60 {
+            let last = if i < 40 { i + 60 } else { i };
+            let value = root.get_value(&format!("key_{}", i)).unwrap();
+            assert_eq!(value.as_num(), Some(JsonNum::Int(last)));
+        }
+        assert!(root.get_value("key_60").is_none());
+        assert_eq!(root.to_value(), JsonValue::Object(crate::parse(json)));
+    }
+
+    #[test]
+    fn last_duplicate_key_wins() {
+        let document = Document::parse("{\"a\": 1, \"b\": 2, \"a\": 3}").unwrap();
+        let root = document.root();
+        assert_eq!(root.len(), 3);
+        assert_eq!(root.get_value("a").unwrap().as_num(), Some(JsonNum::Int(3)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Node id from another document")]
+    fn rejects_ids_of_other_documents() {
+        let first = Document::parse("[1, [2]]").unwrap();
+        let second = Document::parse("[1, [2]]").unwrap();
+        second.node(first.root().id());
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        assert!(matches!(
+            Document::parse("[1, 2] 3"),
+            Err(Error::Syntax { offset: 7, .. })
+        ));
+        assert!(matches!(
+            Document::parse("{\"a\": [1, }"),
+            Err(Error::Syntax { offset: 10, .. })
+        ));
+    }
+}
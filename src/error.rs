@@ -0,0 +1,110 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// Errors returned by the crate. Malformed input given to `rson::parse`
+/// still panics.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Syntax {
+        message: String,
+        offset: u64,
+    },
+    /// The source file changed after an index of it was built.
+    StaleIndex,
+    /// A progress callback asked for parsing to stop.
+    Cancelled,
+    /// A JSON Pointer that is neither empty nor starts with a slash.
+    InvalidPointer(String),
+    /// An edit at the empty pointer, which would replace the object itself.
+    EmptyPath,
+    /// Some part of the pointer an edit is at does not exist.
+    NotFound(String),
+    /// The pointer an edit is at goes through a value that is not an array
+    /// or object.
+    NotAContainer(String),
+    /// An array index in the pointer an edit is at that is not a number or
+    /// is past the end.
+    InvalidIndex(String),
+    /// An edit adding a key the object already has.
+    KeyExists(String),
+    /// A recorded change whose path holds a different value, or the key at
+    /// a different position, than the change was made from.
+    Conflict(String),
+    /// A NaN or infinite float, which JSON has no way to write.
+    NonFiniteNumber(f64),
+    /// A value was written inside an object without a key before it.
+    ValueWithoutKey,
+    /// A key was written outside of an object.
+    KeyOutsideObject,
+    /// A key was followed by another key or the end of its object.
+    KeyWithoutValue,
+    /// An end that does not match the innermost open array or object.
+    UnbalancedEnd,
+    /// A second value was written after the top level value was complete.
+    MultipleRoots,
+    /// A document was finished with open arrays or objects, or nothing
+    /// written.
+    Incomplete,
+    /// A document too large for the representation it was read into,
+    /// such as a tape of more than 2^32 entries.
+    TooLarge,
+}
+
+impl Error {
+    pub fn syntax(message: String, offset: u64) -> Error {
+        Error::Syntax { message, offset }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(formatter, "IO error: {}", err),
+            Error::Syntax { message, offset } => {
+                write!(formatter, "Syntax error at byte {}: {}", offset, message)
+            }
+            Error::StaleIndex => formatter.write_str("Source changed since the index was built"),
+            Error::Cancelled => formatter.write_str("Parsing was cancelled"),
+            Error::InvalidPointer(pointer) => {
+                write!(formatter, "JSON pointer must start with a slash: {}", pointer)
+            }
+            Error::EmptyPath => formatter.write_str("Path must not be empty"),
+            Error::NotFound(path) => write!(formatter, "Nothing found at {}", path),
+            Error::NotAContainer(path) => {
+                write!(formatter, "Not an array or object at {}", path)
+            }
+            Error::InvalidIndex(path) => write!(formatter, "Invalid array index in {}", path),
+            Error::KeyExists(path) => write!(formatter, "Key already exists at {}", path),
+            Error::Conflict(path) => {
+                write!(formatter, "Value at {} differs from the one changed", path)
+            }
+            Error::NonFiniteNumber(num) => {
+                write!(formatter, "{} can't be written as JSON, which has no NaN or infinity", num)
+            }
+            Error::ValueWithoutKey => formatter.write_str("Value inside object without key"),
+            Error::KeyOutsideObject => formatter.write_str("Key outside of object"),
+            Error::KeyWithoutValue => formatter.write_str("Key without value"),
+            Error::UnbalancedEnd => formatter.write_str("Unbalanced end of array or object"),
+            Error::MultipleRoots => formatter.write_str("More than one top level value"),
+            Error::Incomplete => formatter.write_str("Document is not complete"),
+            Error::TooLarge => formatter.write_str("Document is too large"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,271 @@
+//! Streaming extraction of values at given paths, without building a DOM.
+
+use std::io::Read;
+use std::ops::Range;
+
+use super::error::Error;
+use super::lexer::{Delimiters, JsonTokenType, Token, TokenReader};
+use super::parser;
+use super::pointer;
+use super::types::JsonValue;
+
+const WILDCARD: &str = "*";
+
+/// Streams through `reader` and returns the values found at `paths`, in
+/// document order, together with the concrete pointer of each value.
+///
+/// Paths are JSON Pointers where a `*` token matches any key or index,
+/// so `/dependencies/*` returns every dependency. Only the tokens of the
+/// matching values are kept around, everything else is skipped over as
+/// it is read, so memory use depends on the size of the extracted values
+/// rather than the size of the document.
+pub fn extract<R: Read>(reader: R, paths: &[&str]) -> Result<Vec<(String, JsonValue)>, Error> {
+    let mut extractor = Extractor {
+        tokens: TokenReader::new(reader),
+        patterns: paths
+            .iter()
+            .map(|path| pointer::split(path))
+            .collect::<Result<_, _>>()?,
+        path: vec![],
+        found: vec![],
+    };
+
+    let first = extractor.tokens.expect_token()?;
+    extractor.value(first)?;
+    if let Some((token, range)) = extractor.tokens.next_token()? {
+        let message = format!("Unexpected token after end of document: {:?}", token);
+        return Err(Error::syntax(message, range.start));
+    }
+
+    Ok(extractor.found)
+}
+
+struct Extractor<R: Read> {
+    tokens: TokenReader<R>,
+    patterns: Vec<Vec<String>>,
+    path: Vec<String>,
+    found: Vec<(String, JsonValue)>,
+}
+
+fn token_matches(pattern: &str, token: &str) -> bool {
+    pattern == WILDCARD || pattern == token
+}
+
+impl<R: Read> Extractor<R> {
+    /// Whether some path matches the current path exactly.
+    fn is_match(&self) -> bool {
+        self.patterns.iter().any(|pattern| {
+            pattern.len() == self.path.len()
+                && pattern
+                    .iter()
+                    .zip(&self.path)
+                    .all(|(pattern, token)| token_matches(pattern, token))
+        })
+    }
+
+    /// Whether some path could match below the current path.
+    fn is_prefix(&self) -> bool {
+        self.patterns.iter().any(|pattern| {
+            pattern.len() > self.path.len()
+                && pattern
+                    .iter()
+                    .zip(&self.path)
+                    .all(|(pattern, token)| token_matches(pattern, token))
+        })
+    }
+
+    fn value(&mut self, first: (Token, Range<u64>)) -> Result<(), Error> {
+        match first.0.get_token() {
+            JsonTokenType::Delimiter(Delimiters::LeftBrace)
+            | JsonTokenType::Delimiter(Delimiters::LeftBracket) => {}
+            JsonTokenType::Delimiter(del) => {
+                let message = format!("Unexpected token: {:?}", del);
+                return Err(Error::syntax(message, first.1.start));
+            }
+            _ => {}
+        }
+
+        if self.is_match() {
+            let value = parser::read_value(&mut self.tokens, first, None)?;
+            // other paths may still match further down the extracted value
+            if self.is_prefix() {
+                let path = pointer::join(&self.path);
+                self.found.push((path, value.clone()));
+                self.walk(&value);
+            } else {
+                self.found.push((pointer::join(&self.path), value));
+            }
+            return Ok(());
+        }
+
+        if !self.is_prefix() {
+            return self.skip(first);
+        }
+        match first.0.get_token() {
+            JsonTokenType::Delimiter(Delimiters::LeftBrace) => {
+                self.container(Delimiters::RightBrace, |this, key, next| {
+                    this.path.push(key.unwrap());
+                    this.value(next)?;
+                    this.path.pop();
+                    Ok(())
+                })
+            }
+            JsonTokenType::Delimiter(Delimiters::LeftBracket) => {
+                let mut index = 0usize;
+                self.container(Delimiters::RightBracket, |this, _, next| {
+                    this.path.push(index.to_string());
+                    index += 1;
+                    this.value(next)?;
+                    this.path.pop();
+                    Ok(())
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the members of an object or the elements of an array up to
+    /// `close`, checking the commas and colons between them, and calls
+    /// `member` with each key, if any, and the first token of each value.
+    fn container<F>(&mut self, close: Delimiters, mut member: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut Self, Option<String>, (Token, Range<u64>)) -> Result<(), Error>,
+    {
+        let is_object = close == Delimiters::RightBrace;
+        let mut first_member = true;
+        loop {
+            let mut next = self.tokens.expect_token()?;
+            if first_member && next.0.get_token() == JsonTokenType::Delimiter(close.clone()) {
+                return Ok(());
+            }
+            first_member = false;
+
+            let key = if is_object {
+                let key = match next.0.get_token() {
+                    JsonTokenType::String(key) => key,
+                    unexpected => {
+                        let message =
+                            format!("Expected key to be string, instead got: {:?}", unexpected);
+                        return Err(Error::syntax(message, next.1.start));
+                    }
+                };
+                self.tokens.expect_delimiter(&[Delimiters::Colon])?;
+                next = self.tokens.expect_token()?;
+                Some(key)
+            } else {
+                None
+            };
+            member(self, key, next)?;
+
+            if self
+                .tokens
+                .expect_delimiter(&[Delimiters::Comma, close.clone()])?
+                == close
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Reads past the value starting with `first`, checking its syntax
+    /// without keeping any of it.
+    fn skip(&mut self, first: (Token, Range<u64>)) -> Result<(), Error> {
+        match first.0.get_token() {
+            JsonTokenType::Delimiter(Delimiters::LeftBrace) => {
+                self.container(Delimiters::RightBrace, |this, _, next| this.skip(next))
+            }
+            JsonTokenType::Delimiter(Delimiters::LeftBracket) => {
+                self.container(Delimiters::RightBracket, |this, _, next| this.skip(next))
+            }
+            JsonTokenType::Delimiter(del) => {
+                let message = format!("Unexpected token: {:?}", del);
+                Err(Error::syntax(message, first.1.start))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Finds the matches below an already extracted value.
+    fn walk(&mut self, value: &JsonValue) {
+        let children: Vec<(String, &JsonValue)> = match value {
+            JsonValue::Object(object) => object
+                .to_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+            JsonValue::Vec(vec) => vec
+                .iter()
+                .enumerate()
+                .map(|(index, value)| (index.to_string(), value))
+                .collect(),
+            _ => vec![],
+        };
+
+        for (key, child) in children {
+            self.path.push(key);
+            if self.is_match() {
+                self.found.push((pointer::join(&self.path), child.clone()));
+            }
+            if self.is_prefix() {
+                self.walk(child);
+            }
+            self.path.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::JsonNum;
+    use std::fs::File;
+
+    #[test]
+    fn extract_from_package() {
+        let file = File::open("./test_files/test_package.json").unwrap();
+        let found = extract(file, &["/name", "/scripts/build", "/missing"]).unwrap();
+        let paths: Vec<&str> = found.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, vec!["/name", "/scripts/build"]);
+
+        let package =
+            crate::parse(std::fs::read_to_string("./test_files/test_package.json").unwrap());
+        assert_eq!(&found[0].1, package.get_value("name").unwrap());
+    }
+
+    #[test]
+    fn extract_with_wildcards() {
+        let json = "{\"a\": [{\"id\": 1}, {\"id\": 2, \"x\": [3]}], \"b\": {\"id\": 4}}";
+        let found = extract(json.as_bytes(), &["/a/*/id", "/*/x/0"]).unwrap();
+        assert_eq!(
+            found,
+            vec![
+                ("/a/0/id".to_string(), JsonValue::Num(JsonNum::Int(1))),
+                ("/a/1/id".to_string(), JsonValue::Num(JsonNum::Int(2))),
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_nested_match() {
+        let json = "{\"a\": {\"b\": [true, null]}}";
+        let found = extract(json.as_bytes(), &["/a", "/a/b/1"]).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1], ("/a/b/1".to_string(), JsonValue::Null));
+    }
+
+    #[test]
+    fn report_syntax_error() {
+        let malformed = [
+            ("{\"a\": [1, 2}", 11),
+            ("{\"a\": [1 2]}", 9),
+            ("{\"a\": {\"x\" 1}}", 11),
+            ("{\"b\": [1,, 2]}", 9),
+            ("{\"b\": {\"x\": 1 \"y\": 2}}", 14),
+        ];
+        for (json, expected) in &malformed {
+            match extract(json.as_bytes(), &["/b"]) {
+                Err(Error::Syntax { offset, .. }) => assert_eq!(offset, *expected, "{}", json),
+                other => panic!("Expected syntax error for {}, got {:?}", json, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,427 @@
+//! Persistent byte offset indexes for random access into large files.
+//!
+//! An index is built once by streaming through the source file, and maps
+//! the JSON Pointer of every value down to a chosen depth to the byte range
+//! it spans. It can be saved next to the source and loaded again by later
+//! runs, which then seek straight to the value they want and parse only
+//! that. The length and modification time of the source are stored with
+//! the index, so a changed source is noticed and the index rebuilt.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use super::error::Error;
+use super::lexer::{Delimiters, JsonTokenType, Token, TokenReader};
+use super::parser;
+use super::pointer;
+use super::types::JsonValue;
+
+const MAGIC: &[u8; 8] = b"RSONIDX\x01";
+
+/// What identifies the version of the source file an index was built from.
+#[derive(Clone, Debug, PartialEq)]
+struct SourceStamp {
+    len: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
+
+impl SourceStamp {
+    fn of(source: &Path) -> Result<SourceStamp, Error> {
+        let metadata = fs::metadata(source)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(SourceStamp {
+            len: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct DocumentIndex {
+    source: PathBuf,
+    stamp: SourceStamp,
+    max_depth: u32,
+    entries: HashMap<String, Range<u64>>,
+}
+
+impl DocumentIndex {
+    /// Streams through `source` and records the byte range of every value
+    /// at most `max_depth` levels below the root. A depth of 1 indexes the
+    /// members of the root, 2 also the members or elements of those, etc.
+    pub fn build(source: &Path, max_depth: u32) -> Result<DocumentIndex, Error> {
+        let stamp = SourceStamp::of(source)?;
+        let mut builder = IndexBuilder {
+            tokens: TokenReader::new(BufReader::new(File::open(source)?)),
+            max_depth,
+            path: vec![],
+            entries: HashMap::new(),
+        };
+
+        let first = builder.tokens.expect_token()?;
+        builder.value(first)?;
+        if let Some((token, range)) = builder.tokens.next_token()? {
+            let message = format!("Unexpected token after end of document: {:?}", token);
+            return Err(Error::syntax(message, range.start));
+        }
+
+        Ok(DocumentIndex {
+            source: source.to_path_buf(),
+            stamp,
+            max_depth,
+            entries: builder.entries,
+        })
+    }
+
+    /// Loads the index at `index_path` if it is still up to date with
+    /// `source` and was built with `max_depth`, otherwise builds a new
+    /// one and saves it there. An index file that can't be loaded, such
+    /// as one that is truncated or corrupt, is replaced the same way.
+    pub fn open(source: &Path, index_path: &Path, max_depth: u32) -> Result<DocumentIndex, Error> {
+        if let Ok(index) = DocumentIndex::load(source, index_path) {
+            if index.max_depth == max_depth {
+                return Ok(index);
+            }
+        }
+
+        let index = DocumentIndex::build(source, max_depth)?;
+        index.save(index_path)?;
+        Ok(index)
+    }
+
+    /// Loads a saved index, failing with `Error::StaleIndex` if `source`
+    /// has changed since it was built.
+    pub fn load(source: &Path, index_path: &Path) -> Result<DocumentIndex, Error> {
+        let mut reader = BufReader::new(File::open(index_path)?);
+
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::syntax("Not an rson index file".to_string(), 0));
+        }
+
+        let stamp = SourceStamp {
+            len: read_u64(&mut reader)?,
+            modified_secs: read_u64(&mut reader)?,
+            modified_nanos: read_u32(&mut reader)?,
+        };
+        if stamp != SourceStamp::of(source)? {
+            return Err(Error::StaleIndex);
+        }
+
+        let corrupt = |message: &str| Error::syntax(format!("Corrupt index: {}", message), 0);
+        let max_depth = read_u32(&mut reader)?;
+        let entry_count = read_u64(&mut reader)?;
+        // the counts are only trusted as far as the data is actually there
+        let mut entries = HashMap::with_capacity(entry_count.min(1024) as usize);
+        for _ in 0..entry_count {
+            let pointer_len = read_u32(&mut reader)? as u64;
+            let mut pointer = Vec::with_capacity(pointer_len.min(1024) as usize);
+            if reader.by_ref().take(pointer_len).read_to_end(&mut pointer)? as u64 != pointer_len {
+                return Err(corrupt("pointer is cut off"));
+            }
+            let pointer =
+                String::from_utf8(pointer).map_err(|_| corrupt("pointer is not valid UTF-8"))?;
+            let start = read_u64(&mut reader)?;
+            let end = read_u64(&mut reader)?;
+            if start > end || end > stamp.len {
+                return Err(corrupt("range is outside the source"));
+            }
+            entries.insert(pointer, start..end);
+        }
+        if reader.read(&mut [0u8])? != 0 {
+            return Err(corrupt("unexpected data after the entries"));
+        }
+
+        Ok(DocumentIndex {
+            source: source.to_path_buf(),
+            stamp,
+            max_depth,
+            entries,
+        })
+    }
+
+    pub fn save(&self, index_path: &Path) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(index_path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.stamp.len.to_le_bytes())?;
+        writer.write_all(&self.stamp.modified_secs.to_le_bytes())?;
+        writer.write_all(&self.stamp.modified_nanos.to_le_bytes())?;
+        writer.write_all(&self.max_depth.to_le_bytes())?;
+        writer.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+
+        // in document order, so the same source always gives the same file
+        let mut entries: Vec<(&String, &Range<u64>)> = self.entries.iter().collect();
+        entries.sort_by_key(|(pointer, range)| (range.start, pointer.len()));
+        for (pointer, range) in entries {
+            writer.write_all(&(pointer.len() as u32).to_le_bytes())?;
+            writer.write_all(pointer.as_bytes())?;
+            writer.write_all(&range.start.to_le_bytes())?;
+            writer.write_all(&range.end.to_le_bytes())?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Whether the source file is unchanged since the index was built.
+    pub fn is_fresh(&self) -> Result<bool, Error> {
+        Ok(SourceStamp::of(&self.source)? == self.stamp)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The byte range of the value at `pointer`, if it was indexed.
+    pub fn range(&self, pointer: &str) -> Option<Range<u64>> {
+        self.entries.get(pointer).cloned()
+    }
+
+    /// Seeks to the value at `pointer` and parses only that value.
+    pub fn read(&self, pointer: &str) -> Result<Option<JsonValue>, Error> {
+        pointer::split(pointer)?;
+        let range = match self.range(pointer) {
+            Some(range) => range,
+            None => return Ok(None),
+        };
+        if !self.is_fresh()? {
+            return Err(Error::StaleIndex);
+        }
+
+        let mut file = File::open(&self.source)?;
+        file.seek(SeekFrom::Start(range.start))?;
+        let mut tokens = TokenReader::new(file.take(range.end - range.start));
+        let value = tokens.expect_token().and_then(|first| {
+            let value = parser::read_value(&mut tokens, first, None)?;
+            match tokens.next_token()? {
+                Some((token, range)) => {
+                    let message = format!("Unexpected token after end of value: {:?}", token);
+                    Err(Error::syntax(message, range.start))
+                }
+                None => Ok(value),
+            }
+        });
+
+        // offsets from the reader are relative to the start of the value
+        value.map(Some).map_err(|err| match err {
+            Error::Syntax { message, offset } => Error::syntax(message, range.start + offset),
+            err => err,
+        })
+    }
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+struct IndexBuilder<R: Read> {
+    tokens: TokenReader<R>,
+    max_depth: u32,
+    path: Vec<String>,
+    entries: HashMap<String, Range<u64>>,
+}
+
+impl<R: Read> IndexBuilder<R> {
+    /// Scans the value starting with `first`, recording its range if it is
+    /// shallow enough, and returns the offset just after it.
+    fn value(&mut self, first: (Token, Range<u64>)) -> Result<u64, Error> {
+        let (token, range) = first;
+        let end = match token.get_token() {
+            JsonTokenType::Delimiter(Delimiters::LeftBrace) => self.object()?,
+            JsonTokenType::Delimiter(Delimiters::LeftBracket) => self.array()?,
+            JsonTokenType::Delimiter(del) => {
+                let message = format!("Unexpected token: {:?}", del);
+                return Err(Error::syntax(message, range.start));
+            }
+            _ => range.end,
+        };
+
+        if self.path.len() as u32 <= self.max_depth {
+            self.entries
+                .insert(pointer::join(&self.path), range.start..end);
+        }
+        Ok(end)
+    }
+
+    fn object(&mut self) -> Result<u64, Error> {
+        let mut first_member = true;
+        loop {
+            let (token, range) = self.tokens.expect_token()?;
+            let key = match token.get_token() {
+                JsonTokenType::Delimiter(Delimiters::RightBrace) if first_member => {
+                    return Ok(range.end)
+                }
+                JsonTokenType::String(key) => key,
+                unexpected => {
+                    let message =
+                        format!("Expected key to be string, instead got: {:?}", unexpected);
+                    return Err(Error::syntax(message, range.start));
+                }
+            };
+            first_member = false;
+
+            self.tokens.expect_delimiter(&[Delimiters::Colon])?;
+            let next = self.tokens.expect_token()?;
+            self.path.push(key);
+            self.value(next)?;
+            self.path.pop();
+
+            if self
+                .tokens
+                .expect_delimiter(&[Delimiters::Comma, Delimiters::RightBrace])?
+                == Delimiters::RightBrace
+            {
+                return Ok(self.tokens.offset());
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<u64, Error> {
+        let mut index = 0usize;
+        loop {
+            let next = self.tokens.expect_token()?;
+            if index == 0
+                && next.0.get_token() == JsonTokenType::Delimiter(Delimiters::RightBracket)
+            {
+                return Ok(next.1.end);
+            }
+
+            self.path.push(index.to_string());
+            self.value(next)?;
+            self.path.pop();
+            index += 1;
+
+            if self
+                .tokens
+                .expect_delimiter(&[Delimiters::Comma, Delimiters::RightBracket])?
+                == Delimiters::RightBracket
+            {
+                return Ok(self.tokens.offset());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("rson-{}-{}", std::process::id(), name))
+    }
+
+    const DOCUMENT: &str = "{
+        \"name\": \"archive\",
+        \"records\": [
+            {\"id\": 0, \"tags\": [\"a\"]},
+            {\"id\": 1, \"tags\": [\"b\", \"c\"]}
+        ],
+        \"a/b\": 13.37
+    }";
+
+    #[test]
+    fn build_and_read() {
+        let source = temp_path("build.json");
+        fs::write(&source, DOCUMENT).unwrap();
+
+        let index = DocumentIndex::build(&source, 2).unwrap();
+        assert!(index.range("/records/1/id").is_none());
+        assert_eq!(
+            &DOCUMENT[index.range("/records/1").unwrap().start as usize..][..9],
+            "{\"id\": 1,"
+        );
+
+        let full = crate::parse(DOCUMENT.to_string());
+        let records = full.get_value("records").unwrap();
+        assert_eq!(index.read("/records").unwrap().as_ref(), Some(records));
+        assert_eq!(
+            index.read("/a~1b").unwrap(),
+            Some(JsonValue::Num(crate::types::JsonNum::Float(13.37)))
+        );
+
+        fs::remove_file(source).unwrap();
+    }
+
+    #[test]
+    fn save_load_and_invalidate() {
+        let source = temp_path("save.json");
+        let index_path = temp_path("save.json.idx");
+        fs::write(&source, DOCUMENT).unwrap();
+
+        let built = DocumentIndex::open(&source, &index_path, 1).unwrap();
+        let loaded = DocumentIndex::load(&source, &index_path).unwrap();
+        assert_eq!(loaded.entries, built.entries);
+
+        fs::write(&source, "{\"records\": [1, 2]}").unwrap();
+        assert!(matches!(
+            DocumentIndex::load(&source, &index_path),
+            Err(Error::StaleIndex)
+        ));
+        assert!(matches!(built.read("/records"), Err(Error::StaleIndex)));
+
+        let rebuilt = DocumentIndex::open(&source, &index_path, 1).unwrap();
+        assert_eq!(rebuilt.range("/records"), Some(12..18));
+
+        fs::remove_file(source).unwrap();
+        fs::remove_file(index_path).unwrap();
+    }
+
+    #[test]
+    fn rebuild_corrupt_index() {
+        let source = temp_path("corrupt.json");
+        let index_path = temp_path("corrupt.json.idx");
+        fs::write(&source, DOCUMENT).unwrap();
+        let built = DocumentIndex::open(&source, &index_path, 2).unwrap();
+        let saved = fs::read(&index_path).unwrap();
+
+        let mut huge_count = saved.clone();
+        huge_count[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        for corrupt in &[&saved[..saved.len() - 3], &huge_count[..], b"garbage"] {
+            fs::write(&index_path, corrupt).unwrap();
+            assert!(DocumentIndex::load(&source, &index_path).is_err());
+            let rebuilt = DocumentIndex::open(&source, &index_path, 2).unwrap();
+            assert_eq!(rebuilt.entries, built.entries);
+            assert_eq!(fs::read(&index_path).unwrap(), saved);
+        }
+
+        assert!(matches!(
+            built.read("records"),
+            Err(Error::InvalidPointer(_))
+        ));
+        fs::remove_file(source).unwrap();
+        fs::remove_file(index_path).unwrap();
+    }
+
+    #[test]
+    fn report_syntax_error_offset() {
+        let source = temp_path("broken.json");
+        fs::write(&source, "{\"key\" 1}").unwrap();
+        match DocumentIndex::build(&source, 1) {
+            Err(Error::Syntax { offset, .. }) => assert_eq!(offset, 7),
+            other => panic!("Expected syntax error, got: {:?}", other),
+        }
+        fs::remove_file(source).unwrap();
+    }
+}
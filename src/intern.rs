@@ -0,0 +1,96 @@
+//! Key interning, so the keys repeated across thousands of records in a
+//! document share one allocation instead of each getting their own.
+
+use std::collections::HashSet;
+use std::io::Read;
+use std::sync::Arc;
+
+use super::error::Error;
+use super::lexer::TokenReader;
+use super::parser;
+use super::types::JsonObject;
+
+/// A table of the keys seen so far, handing out shared copies of them.
+#[derive(Debug, Default)]
+pub struct KeyInterner {
+    keys: HashSet<Arc<str>>,
+}
+
+impl KeyInterner {
+    pub fn new() -> KeyInterner {
+        KeyInterner::default()
+    }
+
+    /// Returns the shared copy of `key`, adding it if it is new.
+    pub fn intern(&mut self, key: &str) -> Arc<str> {
+        if let Some(shared) = self.keys.get(key) {
+            return shared.clone();
+        }
+        let shared: Arc<str> = Arc::from(key);
+        self.keys.insert(shared.clone());
+        shared
+    }
+
+    /// The number of distinct keys.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// Like `rson::parse`, but equal keys share one allocation and syntax
+/// errors are returned instead of panicking.
+pub fn parse_interned(json_content: String) -> Result<JsonObject, Error> {
+    parse_reader_interned(json_content.as_bytes(), &mut KeyInterner::new())
+}
+
+/// Parses a top level object from `reader`, sharing keys through
+/// `interner`, which can be kept to share keys across several documents.
+pub fn parse_reader_interned<R: Read>(
+    reader: R,
+    interner: &mut KeyInterner,
+) -> Result<JsonObject, Error> {
+    parser::read_document(&mut TokenReader::new(reader), Some(interner))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::JsonValue;
+    use std::fs;
+
+    #[test]
+    fn records_share_keys() {
+        let records: Vec<String> = (0..100)
+            .map(|i| format!("{{\"id\": {}, \"name\": \"n{}\"}}", i, i))
+            .collect();
+        let json = format!("{{\"records\": [{}]}}", records.join(", "));
+
+        let mut interner = KeyInterner::new();
+        let object = parse_reader_interned(json.as_bytes(), &mut interner).unwrap();
+        assert_eq!(object, crate::parse(json));
+        assert_eq!(interner.len(), 3);
+
+        // every record, the interner and the table's own copy hold a reference
+        let id = interner.intern("id");
+        assert_eq!(Arc::strong_count(&id), 100 + 2);
+
+        match object.get_value("records") {
+            Some(JsonValue::Vec(records)) => assert_eq!(records.len(), 100),
+            other => panic!("Expected records, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn same_result_as_parse() {
+        let json = fs::read_to_string("./test_files/test_package.json").unwrap();
+        assert_eq!(parse_interned(json.clone()).unwrap(), crate::parse(json));
+        assert!(matches!(
+            parse_interned("{\"a\": }".to_string()),
+            Err(Error::Syntax { offset: 6, .. })
+        ));
+    }
+}
@@ -0,0 +1,461 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use super::error::Error;
+use super::lexer::TokenReader;
+use super::parser;
+use super::scan::{
+    is_number, is_whitespace, parse_number, skip_string, skip_whitespace, try_unescape,
+};
+use super::types::{JsonNum, JsonValue};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LazyKind {
+    Object,
+    Array,
+    String,
+    Number,
+    Bool,
+    Null,
+}
+
+/// Where a value or key is in the source. Values inside a container
+/// follow it, so `next` is both the end of its subtree and the node of
+/// its next sibling.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Node {
+    start: usize,
+    end: usize,
+    next: usize,
+}
+
+/// A document that has been checked and skimmed, but not parsed.
+///
+/// One pass over the input checks that it is valid JSON and records the
+/// byte range of every value and key in it. Nothing else is done up
+/// front: members and elements are found by jumping between the
+/// recorded ranges, and strings and numbers are only decoded when they
+/// are read, so the parts of a document that are never touched are
+/// never allocated.
+#[derive(Debug)]
+pub struct LazyDocument<'a> {
+    source: &'a str,
+    nodes: Vec<Node>,
+}
+
+impl<'a> LazyDocument<'a> {
+    pub fn new(source: &'a str) -> Result<LazyDocument<'a>, Error> {
+        let mut skimmer = Skimmer {
+            source,
+            pos: 0,
+            nodes: vec![],
+        };
+        skimmer.value()?;
+        if skimmer.peek().is_some() {
+            let message = format!("Unexpected input after value: {}", &source[skimmer.pos..]);
+            return Err(skimmer.error(message));
+        }
+
+        Ok(LazyDocument {
+            source,
+            nodes: skimmer.nodes,
+        })
+    }
+
+    pub fn root(&self) -> LazyValue<'_> {
+        LazyValue {
+            source: self.source,
+            nodes: &self.nodes,
+            node: 0,
+        }
+    }
+}
+
+/// A view of one value of a `LazyDocument`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LazyValue<'a> {
+    source: &'a str,
+    nodes: &'a [Node],
+    node: usize,
+}
+
+impl<'a> LazyValue<'a> {
+    fn at(&self, node: usize) -> LazyValue<'a> {
+        LazyValue { node, ..*self }
+    }
+
+    /// The raw, undecoded JSON text of this value.
+    pub fn raw(&self) -> &'a str {
+        let node = self.nodes[self.node];
+        &self.source[node.start..node.end]
+    }
+
+    pub fn kind(&self) -> LazyKind {
+        match self.raw().as_bytes()[0] {
+            b'{' => LazyKind::Object,
+            b'[' => LazyKind::Array,
+            b'"' => LazyKind::String,
+            b't' | b'f' => LazyKind::Bool,
+            b'n' => LazyKind::Null,
+            _ => LazyKind::Number,
+        }
+    }
+
+    /// The nodes directly inside this one, keys and values alternating
+    /// for objects.
+    fn children(&self, kind: LazyKind) -> LazyIter<'a> {
+        let (next, end) = if self.kind() == kind {
+            (self.node + 1, self.nodes[self.node].next)
+        } else {
+            (0, 0)
+        };
+        LazyIter {
+            value: *self,
+            next,
+            end,
+        }
+    }
+
+    /// Looks up `key` if this is an object, jumping over the values of
+    /// the other members. Of repeated keys the last one counts, as in
+    /// `rson::parse`.
+    pub fn get(&self, key: &str) -> Option<LazyValue<'a>> {
+        self.entries()
+            .filter(|(entry_key, _)| entry_key == key)
+            .last()
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the element at `index` if this is an array.
+    pub fn index(&self, index: usize) -> Option<LazyValue<'a>> {
+        self.iter().nth(index)
+    }
+
+    /// Iterates over the elements of an array, or nothing for other types.
+    pub fn iter(&self) -> LazyIter<'a> {
+        self.children(LazyKind::Array)
+    }
+
+    /// Iterates over the members of an object, or nothing for other types.
+    pub fn entries(&self) -> LazyEntries<'a> {
+        LazyEntries {
+            iter: self.children(LazyKind::Object),
+        }
+    }
+
+    /// The members of an object with their keys still undecoded.
+    pub(crate) fn members(&self) -> impl Iterator<Item = (LazyValue<'a>, LazyValue<'a>)> {
+        let mut iter = self.children(LazyKind::Object);
+        std::iter::from_fn(move || Some((iter.next()?, iter.next()?)))
+    }
+
+    /// Decodes the string, only allocating if it contains escapes.
+    pub fn as_str(&self) -> Option<Cow<'a, str>> {
+        if self.kind() != LazyKind::String {
+            return None;
+        }
+        let raw = self.raw();
+        // the escapes were checked when the document was skimmed
+        try_unescape(&raw[1..raw.len() - 1]).ok()
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        if self.kind() != LazyKind::Number {
+            return None;
+        }
+        self.raw().parse::<i64>().ok()
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        if self.kind() != LazyKind::Number {
+            return None;
+        }
+        self.raw().parse::<f64>().ok()
+    }
+
+    /// Decodes the number like `rson::parse` would. `None` for integers
+    /// that don't fit an `i64`.
+    pub fn as_num(&self) -> Option<JsonNum> {
+        if self.kind() != LazyKind::Number {
+            return None;
+        }
+        parse_number(self.raw())
+    }
+
+    /// Where this value is in the source.
+    pub(crate) fn range(&self) -> Range<usize> {
+        let node = self.nodes[self.node];
+        node.start..node.end
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.raw() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.raw() == "null"
+    }
+
+    /// Fully parses this value and everything below it. Fails where
+    /// `rson::parse` would, such as on integers that don't fit an `i64`.
+    pub fn to_value(&self) -> Result<JsonValue, Error> {
+        let start = self.nodes[self.node].start as u64;
+        let mut tokens = TokenReader::new(self.raw().as_bytes());
+        let first = tokens.expect_token();
+        first
+            .and_then(|first| parser::read_value(&mut tokens, first, None))
+            .map_err(|err| match err {
+                Error::Syntax { message, offset } => Error::syntax(message, start + offset),
+                err => err,
+            })
+    }
+}
+
+/// Iterator over the elements of a `LazyValue` array.
+pub struct LazyIter<'a> {
+    value: LazyValue<'a>,
+    next: usize,
+    end: usize,
+}
+
+impl<'a> Iterator for LazyIter<'a> {
+    type Item = LazyValue<'a>;
+
+    fn next(&mut self) -> Option<LazyValue<'a>> {
+        if self.next >= self.end {
+            return None;
+        }
+        let value = self.value.at(self.next);
+        self.next = self.value.nodes[self.next].next;
+        Some(value)
+    }
+}
+
+/// Iterator over the members of a `LazyValue` object, with decoded keys.
+pub struct LazyEntries<'a> {
+    iter: LazyIter<'a>,
+}
+
+impl<'a> Iterator for LazyEntries<'a> {
+    type Item = (Cow<'a, str>, LazyValue<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.iter.next()?.as_str()?;
+        Some((key, self.iter.next()?))
+    }
+}
+
+/// Checks a document and records its nodes in one pass.
+struct Skimmer<'a> {
+    source: &'a str,
+    pos: usize,
+    nodes: Vec<Node>,
+}
+
+impl<'a> Skimmer<'a> {
+    fn error(&self, message: String) -> Error {
+        Error::syntax(message, self.pos as u64)
+    }
+
+    /// The next byte that isn't whitespace.
+    fn peek(&mut self) -> Option<u8> {
+        self.pos = skip_whitespace(self.source.as_bytes(), self.pos);
+        self.source.as_bytes().get(self.pos).copied()
+    }
+
+    /// Records the node starting at the current position, once `skim`
+    /// has moved past it.
+    fn node<F>(&mut self, skim: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Skimmer<'a>) -> Result<(), Error>,
+    {
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            start: self.pos,
+            end: self.pos,
+            next: node + 1,
+        });
+        skim(self)?;
+        self.nodes[node].end = self.pos;
+        self.nodes[node].next = self.nodes.len();
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<(), Error> {
+        match self.peek() {
+            Some(b'{') => self.node(|skimmer| skimmer.container(b'}', true)),
+            Some(b'[') => self.node(|skimmer| skimmer.container(b']', false)),
+            Some(b'"') => self.node(Skimmer::string),
+            Some(b'}') | Some(b']') | Some(b':') | Some(b',') => {
+                let token = self.source.as_bytes()[self.pos] as char;
+                Err(self.error(format!("Unexpected token: {}", token)))
+            }
+            Some(_) => self.node(Skimmer::scalar),
+            None => Err(self.error("Unexpected end of input".to_string())),
+        }
+    }
+
+    fn container(&mut self, close: u8, is_object: bool) -> Result<(), Error> {
+        self.pos += 1;
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            return Ok(());
+        }
+
+        loop {
+            if is_object {
+                if self.peek() != Some(b'"') {
+                    return Err(self.error("Expected key to be string".to_string()));
+                }
+                self.node(Skimmer::string)?;
+                if self.peek() != Some(b':') {
+                    return Err(self.error("Did not get colon after key".to_string()));
+                }
+                self.pos += 1;
+            }
+            self.value()?;
+
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(byte) if byte == close => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                Some(_) => return Err(self.error("Expected comma or end of value".to_string())),
+                None => {
+                    let message = "Unexpected end of input, value not complete";
+                    return Err(self.error(message.to_string()));
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<(), Error> {
+        let end = match skip_string(self.source.as_bytes(), self.pos) {
+            Some(end) => end,
+            None => {
+                self.pos = self.source.len();
+                let message = "Unexpected end of input, string not complete";
+                return Err(self.error(message.to_string()));
+            }
+        };
+        try_unescape(&self.source[self.pos + 1..end - 1]).map_err(|message| self.error(message))?;
+        self.pos = end;
+        Ok(())
+    }
+
+    fn scalar(&mut self) -> Result<(), Error> {
+        let bytes = self.source.as_bytes();
+        let end = (self.pos..bytes.len())
+            .find(|&pos| is_whitespace(bytes[pos]) || b"{}[]:,\"".contains(&bytes[pos]))
+            .unwrap_or(bytes.len());
+        let raw = &self.source[self.pos..end];
+        match raw {
+            "true" | "false" | "null" => {}
+            num if is_number(num) => {}
+            _ => return Err(self.error(format!("Lexer doesn't understand value: {}", raw))),
+        }
+        self.pos = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = "{
+        \"name\": \"rson\",
+        \"version\": 3,
+        \"float\": 13.37,
+        \"files\": [\"lib.rs\", {\"nested\": [1, 2]}, null],
+        \"esc\\\"aped\": \"line\\nbreak\",
+        \"private\": false
+    }";
+
+    fn document() -> LazyDocument<'static> {
+        LazyDocument::new(DOCUMENT).unwrap()
+    }
+
+    #[test]
+    fn get_members() {
+        let document = document();
+        let doc = document.root();
+        assert_eq!(doc.kind(), LazyKind::Object);
+        assert_eq!(doc.get("name").unwrap().as_str().unwrap(), "rson");
+        assert_eq!(doc.get("version").unwrap().as_i64(), Some(3));
+        assert_eq!(doc.get("float").unwrap().as_f64(), Some(13.37));
+        assert_eq!(doc.get("private").unwrap().as_bool(), Some(false));
+        assert!(doc.get("missing").is_none());
+    }
+
+    #[test]
+    fn decode_keys_and_strings() {
+        let document = document();
+        let doc = document.root();
+        let value = doc.get("esc\"aped").unwrap();
+        assert_eq!(value.as_str().unwrap(), "line\nbreak");
+        assert!(matches!(
+            doc.get("name").unwrap().as_str(),
+            Some(Cow::Borrowed(_))
+        ));
+    }
+
+    #[test]
+    fn index_and_iter_arrays() {
+        let document = document();
+        let files = document.root().get("files").unwrap();
+        assert_eq!(files.iter().count(), 3);
+        assert!(files.index(2).unwrap().is_null());
+        assert!(files.index(3).is_none());
+
+        let nested = files.index(1).unwrap().get("nested").unwrap();
+        assert_eq!(nested.raw(), "[1, 2]");
+    }
+
+    #[test]
+    fn to_value_parses_subtree() {
+        let document = document();
+        let files = document.root().get("files").unwrap();
+        let full = crate::parse(DOCUMENT.to_string());
+        assert_eq!(&files.to_value().unwrap(), full.get_value("files").unwrap());
+
+        let document = LazyDocument::new("[1, 99999999999999999999]").unwrap();
+        assert!(document.root().index(0).unwrap().to_value().is_ok());
+        assert!(matches!(
+            document.root().to_value(),
+            Err(Error::Syntax { .. })
+        ));
+    }
+
+    #[test]
+    fn last_duplicate_key_wins() {
+        let document = LazyDocument::new("{\"a\": 1, \"a\": 2.5}").unwrap();
+        let value = document.root().get("a").unwrap();
+        assert_eq!(value.as_num(), Some(JsonNum::Float(2.5)));
+    }
+
+    #[test]
+    fn reject_malformed_documents() {
+        let malformed = [
+            ("{\"a\": [1, 2}", 11),
+            ("[1, 2,]", 6),
+            ("{\"a\": 1,}", 8),
+            ("{\"a\" 1}", 5),
+            ("[1 2]", 3),
+            ("[01]", 1),
+            ("[\"\\x\"]", 1),
+            ("[1] 2", 4),
+        ];
+        for (json, expected) in &malformed {
+            match LazyDocument::new(json) {
+                Err(Error::Syntax { offset, .. }) => assert_eq!(offset, *expected, "{}", json),
+                other => panic!("Expected syntax error for {}, got {:?}", json, other),
+            }
+        }
+    }
+}
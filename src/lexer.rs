@@ -1,4 +1,9 @@
 use core::panic;
+use std::io::{self, Read};
+use std::ops::Range;
+
+use super::error::Error;
+use super::scan::{is_number, is_whitespace, try_unescape};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Delimiters {
@@ -19,6 +24,7 @@ pub enum Numbers {
 #[derive(Debug, PartialEq, Clone)]
 pub enum JsonTokenType {
     Delimiter(Delimiters),
+    /// The decoded contents of a string, see `JsonValue::String`.
     String(String),
     Number(Numbers),
     Boolean(bool),
@@ -54,6 +60,8 @@ impl JsonTokenType {
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     token: JsonTokenType,
+    /// The token as it was written in the input, quotes and escapes
+    /// included.
     raw: String,
 }
 
@@ -65,161 +73,213 @@ impl Token {
         return self.token.clone();
     }
 
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
 }
 
 pub fn lex(json: String) -> Vec<Token> {
-    let mut iter = json.chars().peekable();
-    let mut lookahead: Option<char> = iter.next();
+    let mut reader = TokenReader::new(json.as_bytes());
     let mut token_vec: Vec<Token> = vec![];
     loop {
-        match lookahead {
-            None => break,
-            Some(val) => {
-                match val {
-                    '{' => {
-                        token_vec.push(Token::new(JsonTokenType::new_left_brace(), '{'.to_string()))
-                    }
-                    '}' => token_vec.push(Token::new(
-                        JsonTokenType::new_right_brace(),
-                        '}'.to_string(),
-                    )),
-                    '[' => token_vec.push(Token::new(
-                        JsonTokenType::new_left_bracket(),
-                        '['.to_string(),
-                    )),
-                    ']' => token_vec.push(Token::new(
-                        JsonTokenType::new_right_bracket(),
-                        ']'.to_string(),
-                    )),
-                    ':' => token_vec.push(Token::new(JsonTokenType::new_colon(), ':'.to_string())),
-                    ',' => token_vec.push(Token::new(JsonTokenType::new_comma(), ','.to_string())),
-                    '\n' | ' ' => {} // ignore whitespace
-                    // string
-                    '\"' => {
-                        let mut string_builder = String::new();
-                        string_builder.push(val); // push first quote
-                        loop {
-                            let next_val = iter.next();
-                            if let Some(val) = next_val {
-                                string_builder.push(val);
-
-                                let is_quote = val == '\"';
-                                let is_escaped_quote = string_builder.ends_with("\\\"");
-                                if is_quote && !is_escaped_quote {
-                                    let finished_string = string_builder.to_string();
-                                    token_vec.push(Token::new(
-                                        JsonTokenType::String(finished_string.clone()),
-                                        finished_string.clone(),
-                                    ));
-                                    break;
-                                }
-                            } else {
-                                panic!("Unexpected end of input, string not complete")
-                            }
-                        }
-                    }
-                    // boolean and null
-                    't' | 'f' | 'n' => {
-                        let mut bool_builder = String::new();
-                        bool_builder.push(val);
-
-                        loop {
-                            // peek so we don't consume the following comma
-                            let peek_next_val = iter.peek();
-                            if let Some(value) = peek_next_val {
-                                if value.is_alphabetic() {
-                                    let next_val = iter.next().unwrap();
-                                    bool_builder.push(next_val)
-                                } else {
-                                    break;
-                                }
-                            } else {
-                                panic!("Unexpected end of input, incomplete boolean or null")
-                            }
-                        }
-                        let finished_bool_or_null = bool_builder.to_string();
-                        if finished_bool_or_null.eq("true") {
-                            let bool_token =
-                                Token::new(JsonTokenType::Boolean(true), finished_bool_or_null);
-                            token_vec.push(bool_token);
-                        } else if finished_bool_or_null.eq("false") {
-                            let bool_token =
-                                Token::new(JsonTokenType::Boolean(false), finished_bool_or_null);
-                            token_vec.push(bool_token);
-                        } else if finished_bool_or_null.eq("null") {
-                            let bool_token = Token::new(JsonTokenType::Null, finished_bool_or_null);
-                            token_vec.push(bool_token);
-                        } else {
-                            panic!("Incorrect token found")
-                        }
-                    }
-                    // numbers
-                    num if val.is_numeric() || val.eq(&'-') => {
-                        let mut num_builder = String::new();
-                        num_builder.push(num);
-
-                        loop {
-                            let peeked_next_val = iter.peek();
-                            if let Some(value) = peeked_next_val {
-                                let is_not_num =
-                                    value.eq(&',') || value.eq(&'\n') || value.eq(&']') || value.eq(&'}');
-
-                                // TODO: this doesn't work for hex numbers like 0xx0
-                                if is_not_num {
-                                    let is_float = num_builder.contains(".");
-                                    if is_float {
-                                        let parsed_float = num_builder.parse::<f64>();
-                                        match parsed_float {
-                                            Ok(number) => {
-                                                token_vec.push(Token::new(
-                                                    JsonTokenType::Number(Numbers::Float(number)),
-                                                    number.to_string(),
-                                                ));
-                                                break;
-                                            }
-                                            Err(e) => {
-                                                panic!(
-                                                    "Something went wrong when lexing number: {:?}",
-                                                    e
-                                                )
-                                            }
-                                        }
-                                    } else {
-                                        let parsed_int = num_builder.parse::<i64>();
-                                        match parsed_int {
-                                            Ok(number) => {
-                                                token_vec.push(Token::new(
-                                                    JsonTokenType::Number(Numbers::Integer(number)),
-                                                    number.to_string(),
-                                                ));
-                                                break;
-                                            }
-                                            Err(e) => {
-                                                panic!(
-                                                    "Something went wrong when lexing number: {:?}",
-                                                    e
-                                                )
-                                            }
-                                        }
-                                    }
-                                }
-
-                                num_builder.push(value.to_owned());
-                                iter.next();
-                            } else {
-                                panic!("Unexpected end of input, incomplete num or null")
-                            }
-                        }
-                    }
-                    _ => {
-                        panic!("Lexer doesn't understand value: {}", val)
-                    }
+        match reader.next_token() {
+            Ok(Some((token, _))) => token_vec.push(token),
+            Ok(None) => break,
+            Err(Error::Syntax { message, .. }) => panic!("{}", message),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    return token_vec;
+}
+
+const READ_BUFFER_LEN: usize = 64 * 1024;
+
+/// Lexes tokens one at a time from a reader, keeping track of the byte
+/// offset of every token so large inputs never have to be held in memory.
+pub struct TokenReader<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+    buffer_pos: usize,
+    buffer_len: usize,
+    offset: u64,
+    big_integers: bool,
+}
+
+impl<R: Read> TokenReader<R> {
+    pub fn new(reader: R) -> TokenReader<R> {
+        TokenReader {
+            reader,
+            buffer: vec![0; READ_BUFFER_LEN],
+            buffer_pos: 0,
+            buffer_len: 0,
+            offset: 0,
+            big_integers: false,
+        }
+    }
+
+    /// Accepts integers too big for an `i64` as the nearest float, for
+    /// callers that only need `Token::raw` to be exact.
+    pub fn allow_big_integers(mut self) -> TokenReader<R> {
+        self.big_integers = true;
+        self
+    }
+
+    /// The number of bytes consumed so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn peek_byte(&mut self) -> Result<Option<u8>, Error> {
+        if self.buffer_pos == self.buffer_len {
+            self.buffer_len = loop {
+                match self.reader.read(&mut self.buffer) {
+                    Ok(len) => break len,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(Error::Io(e)),
                 }
+            };
+            self.buffer_pos = 0;
+            if self.buffer_len == 0 {
+                return Ok(None);
             }
         }
+        Ok(Some(self.buffer[self.buffer_pos]))
+    }
 
-        lookahead = iter.next();
+    fn next_byte(&mut self) -> Result<Option<u8>, Error> {
+        let byte = self.peek_byte()?;
+        if byte.is_some() {
+            self.buffer_pos += 1;
+            self.offset += 1;
+        }
+        Ok(byte)
     }
 
-    return token_vec;
+    fn syntax_error(&self, message: &str) -> Error {
+        Error::syntax(message.to_string(), self.offset)
+    }
+
+    /// Reads the rest of a token made up of bytes accepted by `accept`.
+    fn read_while(&mut self, first: u8, accept: fn(u8) -> bool) -> Result<String, Error> {
+        let mut builder = vec![first];
+        while let Some(byte) = self.peek_byte()? {
+            if !accept(byte) {
+                break;
+            }
+            builder.push(byte);
+            self.next_byte()?;
+        }
+        // only ascii bytes are accepted
+        Ok(String::from_utf8(builder).unwrap())
+    }
+
+    /// Reads the rest of a string, returning its decoded contents and the
+    /// raw string with its quotes and escapes.
+    fn read_string(&mut self) -> Result<(String, String), Error> {
+        let mut builder = vec![b'"'];
+        let mut escaped = false;
+        loop {
+            let byte = match self.next_byte()? {
+                Some(byte) => byte,
+                None => {
+                    return Err(self.syntax_error("Unexpected end of input, string not complete"))
+                }
+            };
+            builder.push(byte);
+            match byte {
+                b'"' if !escaped => break,
+                b'\\' => escaped = !escaped,
+                _ => escaped = false,
+            }
+        }
+
+        let raw = String::from_utf8(builder)
+            .map_err(|_| self.syntax_error("String is not valid UTF-8"))?;
+        let contents = try_unescape(&raw[1..raw.len() - 1])
+            .map_err(|message| self.syntax_error(&message))?
+            .into_owned();
+        Ok((contents, raw))
+    }
+
+    /// Like `next_token`, but the end of the input is an error.
+    pub fn expect_token(&mut self) -> Result<(Token, Range<u64>), Error> {
+        match self.next_token()? {
+            Some(next) => Ok(next),
+            None => Err(self.syntax_error("Unexpected end of input")),
+        }
+    }
+
+    /// Reads a token that has to be one of `delimiters`.
+    pub fn expect_delimiter(&mut self, delimiters: &[Delimiters]) -> Result<Delimiters, Error> {
+        let (token, range) = self.expect_token()?;
+        match token.get_token() {
+            JsonTokenType::Delimiter(del) if delimiters.contains(&del) => Ok(del),
+            unexpected => Err(Error::syntax(
+                format!("Unexpected token: {:?}", unexpected),
+                range.start,
+            )),
+        }
+    }
+
+    /// Returns the next token and the byte range it spans, or `None` at
+    /// the end of the input.
+    pub fn next_token(&mut self) -> Result<Option<(Token, Range<u64>)>, Error> {
+        let first = loop {
+            match self.next_byte()? {
+                None => return Ok(None),
+                Some(byte) if is_whitespace(byte) => continue,
+                Some(byte) => break byte,
+            }
+        };
+        let start = self.offset - 1;
+
+        let token = match first {
+            b'{' => Token::new(JsonTokenType::new_left_brace(), '{'.to_string()),
+            b'}' => Token::new(JsonTokenType::new_right_brace(), '}'.to_string()),
+            b'[' => Token::new(JsonTokenType::new_left_bracket(), '['.to_string()),
+            b']' => Token::new(JsonTokenType::new_right_bracket(), ']'.to_string()),
+            b':' => Token::new(JsonTokenType::new_colon(), ':'.to_string()),
+            b',' => Token::new(JsonTokenType::new_comma(), ','.to_string()),
+            b'"' => {
+                let (contents, raw) = self.read_string()?;
+                Token::new(JsonTokenType::String(contents), raw)
+            }
+            b't' | b'f' | b'n' => {
+                let word = self.read_while(first, |byte| byte.is_ascii_alphabetic())?;
+                let token = match word.as_str() {
+                    "true" => JsonTokenType::Boolean(true),
+                    "false" => JsonTokenType::Boolean(false),
+                    "null" => JsonTokenType::Null,
+                    _ => return Err(self.syntax_error("Incorrect token found")),
+                };
+                Token::new(token, word)
+            }
+            b'-' | b'0'..=b'9' => {
+                let num = self.read_while(first, |byte| {
+                    byte.is_ascii_digit() || matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E')
+                })?;
+                let number = if num.contains(['.', 'e', 'E']) {
+                    num.parse::<f64>().ok().map(Numbers::Float)
+                } else if self.big_integers && is_number(&num) {
+                    let int = num.parse::<i64>().ok().map(Numbers::Integer);
+                    int.or_else(|| num.parse::<f64>().ok().map(Numbers::Float))
+                } else {
+                    num.parse::<i64>().ok().map(Numbers::Integer)
+                };
+                match number {
+                    Some(number) => Token::new(JsonTokenType::Number(number), num),
+                    None => {
+                        return Err(self.syntax_error("Something went wrong when lexing number"))
+                    }
+                }
+            }
+            other => {
+                let message = format!("Lexer doesn't understand value: {}", other as char);
+                return Err(Error::syntax(message, start));
+            }
+        };
+
+        Ok(Some((token, start..self.offset)))
+    }
 }
@@ -1,8 +1,37 @@
 use types::JsonObject;
 
+pub mod array;
+pub mod borrowed;
+pub mod change;
+pub mod color;
+pub mod document;
+pub mod error;
+pub mod extract;
+pub mod index;
+pub mod intern;
+pub mod lazy;
 mod lexer;
+mod parallel;
 mod parser;
+pub mod observe;
+pub mod persistent;
+pub mod pointer;
+pub mod progress;
+pub mod reformat;
+mod scan;
+pub mod serializer;
+pub mod structural;
+pub mod summary;
+pub mod tape;
+pub mod transaction;
 pub mod types;
+pub mod writer;
+
+#[cfg(test)]
+mod test_util;
+
+pub use parallel::parse_parallel;
+pub use progress::{parse_reader_with_progress, parse_with_progress};
 
 pub fn parse(json_content: String) -> JsonObject {
     let tokens = lexer::lex(json_content);
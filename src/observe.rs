@@ -0,0 +1,275 @@
+//! A `JsonObject` that tells subscribers about every edit made to it.
+
+use super::change::{self, Change, Container};
+use super::error::Error;
+use super::pointer;
+use super::types::{JsonObject, JsonValue};
+
+/// Identifies a subscription, for `Observed::unsubscribe`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(usize);
+
+struct Subscription {
+    id: SubscriptionId,
+    prefix: Vec<String>,
+    callback: Box<dyn FnMut(&Change)>,
+}
+
+/// Owns an object and edits it on behalf of callers, calling the
+/// subscribers of each edited path with the change that was made.
+pub struct Observed {
+    object: JsonObject,
+    subscriptions: Vec<Subscription>,
+    next_id: usize,
+}
+
+impl Observed {
+    pub fn new(object: JsonObject) -> Observed {
+        Observed {
+            object,
+            subscriptions: vec![],
+            next_id: 0,
+        }
+    }
+
+    pub fn object(&self) -> &JsonObject {
+        &self.object
+    }
+
+    pub fn into_inner(self) -> JsonObject {
+        self.object
+    }
+
+    /// Calls `callback` for every change that can affect the value at
+    /// `prefix`:
+    ///
+    /// - a change at `prefix` or below it, such as `/dependencies/eslint`
+    ///   for the prefix `/dependencies`,
+    /// - one replacing, adding or removing an ancestor of it, such as the
+    ///   whole of `/dependencies` for the prefix `/dependencies/eslint`,
+    ///   which is how emptying the object is reported too,
+    /// - and an insert into or removal from an array before it, which moves
+    ///   it to another index, such as `/files/0` for the prefix `/files/2`.
+    ///
+    /// The callback gets the change as it was made, at its own path. The
+    /// empty prefix matches every change.
+    pub fn subscribe<F>(&mut self, prefix: &str, callback: F) -> Result<SubscriptionId, Error>
+    where
+        F: FnMut(&Change) + 'static,
+    {
+        let prefix = change::split(prefix)?;
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscriptions.push(Subscription {
+            id,
+            prefix,
+            callback: Box::new(callback),
+        });
+        Ok(id)
+    }
+
+    /// Returns whether there was such a subscription.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.subscriptions.len();
+        self.subscriptions
+            .retain(|subscription| subscription.id != id);
+        self.subscriptions.len() < len
+    }
+
+    fn make(&mut self, change: Change) -> Result<(), Error> {
+        let tokens = change::split(change.path())?;
+        let shifts = match change {
+            Change::Insert { .. } | Change::Remove { .. } => matches!(
+                change::locate(&mut self.object, change.path())?.0,
+                Container::Vec(_)
+            ),
+            Change::Replace { .. } => false,
+        };
+        change::apply(&mut self.object, &change)?;
+        for subscription in &mut self.subscriptions {
+            if affects(&tokens, shifts, &subscription.prefix) {
+                (subscription.callback)(&change);
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `JsonObject::insert`, but subscribers of the key are told.
+    pub fn insert(&mut self, key: &str, value: JsonValue) {
+        // a single key always names a member of the root, so this can't fail
+        self.set(&pointer::join(&[key]), value).unwrap();
+    }
+
+    /// Like `JsonObject::delete_key`, telling no one if the key is missing.
+    pub fn delete_key(&mut self, key: &str) {
+        if self.object.get_value(key).is_some() {
+            self.remove(&pointer::join(&[key])).unwrap();
+        }
+    }
+
+    /// Like `JsonObject::empty`, removing the keys last to first.
+    pub fn empty(&mut self) {
+        let keys: Vec<String> = self
+            .object
+            .get_keys()
+            .iter()
+            .map(|key| key.to_string())
+            .collect();
+        for key in keys.iter().rev() {
+            self.delete_key(key);
+        }
+    }
+
+    /// Sets the value at a JSON Pointer, as `Transaction::set` does.
+    pub fn set(&mut self, path: &str, value: JsonValue) -> Result<(), Error> {
+        let change = change::plan_set(&mut self.object, path, value)?;
+        self.make(change)
+    }
+
+    /// Inserts into an array at the index `path` ends in.
+    pub fn insert_element(&mut self, path: &str, value: JsonValue) -> Result<(), Error> {
+        let change = change::plan_insert_element(&mut self.object, path, value)?;
+        self.make(change)
+    }
+
+    /// Removes the key or array element at `path`, telling subscribers
+    /// the value it had.
+    pub fn remove(&mut self, path: &str) -> Result<(), Error> {
+        let change = change::plan_remove(&mut self.object, path)?;
+        self.make(change)
+    }
+}
+
+/// Whether a change at `path` can affect the value at `prefix`. `shifts`
+/// is whether it moves the elements of an array after it.
+fn affects(path: &[String], shifts: bool, prefix: &[String]) -> bool {
+    if path.starts_with(prefix) || prefix.starts_with(path) {
+        return true;
+    }
+    let (index, array) = match path.split_last() {
+        Some(split) if shifts => split,
+        _ => return false,
+    };
+    match (prefix.get(array.len()), index.parse::<usize>()) {
+        (Some(moved), Ok(index)) if prefix.starts_with(array) => {
+            moved.parse::<usize>().map_or(false, |moved| moved >= index)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::int;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Log = Rc<RefCell<Vec<(String, Option<JsonValue>, Option<JsonValue>)>>>;
+
+    fn observed() -> Observed {
+        Observed::new(crate::parse(
+            "{\"name\": \"app\", \"deps\": {\"a\": 1}, \"depsx\": 2}".to_string(),
+        ))
+    }
+
+    fn record(observed: &mut Observed, prefix: &str) -> (Log, SubscriptionId) {
+        let log = Log::default();
+        let sink = log.clone();
+        let id = observed
+            .subscribe(prefix, move |change| {
+                sink.borrow_mut().push((
+                    change.path().to_string(),
+                    change.old_value().cloned(),
+                    change.new_value().cloned(),
+                ))
+            })
+            .unwrap();
+        (log, id)
+    }
+
+    #[test]
+    fn reports_old_and_new_values() {
+        let mut observed = observed();
+        let (log, _) = record(&mut observed, "");
+
+        observed.insert("name", JsonValue::Null);
+        observed.set("/deps/b", JsonValue::Bool(true)).unwrap();
+        observed.delete_key("missing");
+        observed.delete_key("depsx");
+
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                (
+                    "/name".to_string(),
+                    Some(JsonValue::String("app".to_string())),
+                    Some(JsonValue::Null)
+                ),
+                ("/deps/b".to_string(), None, Some(JsonValue::Bool(true))),
+                ("/depsx".to_string(), Some(int(2)), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_by_path_prefix() {
+        let mut observed = observed();
+        let (deps, id) = record(&mut observed, "/deps");
+        let (names, _) = record(&mut observed, "/name");
+
+        observed.set("/deps/a", JsonValue::Null).unwrap();
+        observed.insert("depsx", JsonValue::Null);
+        observed.empty();
+        assert_eq!(deps.borrow().len(), 2);
+        assert_eq!(deps.borrow()[1].0, "/deps");
+        assert_eq!(names.borrow().len(), 1);
+        assert!(observed.object().get_keys().is_empty());
+
+        assert!(observed.unsubscribe(id));
+        assert!(!observed.unsubscribe(id));
+        observed.insert("deps", JsonValue::Null);
+        assert_eq!(deps.borrow().len(), 2);
+    }
+
+    #[test]
+    fn reports_changes_to_ancestors() {
+        let mut observed = observed();
+        let (a, _) = record(&mut observed, "/deps/a");
+
+        observed.set("/deps/b", int(2)).unwrap();
+        observed.insert("deps", JsonValue::Null);
+        observed.insert("deps", JsonValue::Object(JsonObject::new()));
+        observed.empty();
+        let paths: Vec<String> = a.borrow().iter().map(|(path, ..)| path.clone()).collect();
+        assert_eq!(paths, ["/deps", "/deps", "/deps"]);
+        assert_eq!(a.borrow()[2].1, Some(JsonValue::Object(JsonObject::new())));
+    }
+
+    #[test]
+    fn reports_elements_moving() {
+        let mut observed = Observed::new(crate::parse(
+            "{\"files\": [\"a\", \"b\", {\"name\": \"c\"}], \"0\": {}}".to_string(),
+        ));
+        let (second, _) = record(&mut observed, "/files/2/name");
+        let (first, _) = record(&mut observed, "/files/0");
+        let (key, _) = record(&mut observed, "/0/x");
+
+        observed
+            .insert_element("/files/1", JsonValue::Null)
+            .unwrap();
+        observed.remove("/files/0").unwrap();
+        observed.set("/files/-", JsonValue::Null).unwrap();
+        observed.set("/0/y", JsonValue::Null).unwrap();
+        observed.remove("/0/y").unwrap();
+
+        let paths: Vec<String> = second
+            .borrow()
+            .iter()
+            .map(|(path, ..)| path.clone())
+            .collect();
+        assert_eq!(paths, ["/files/1", "/files/0"]);
+        assert_eq!(first.borrow().len(), 1);
+        assert!(key.borrow().is_empty());
+    }
+}
@@ -0,0 +1,343 @@
+use std::ops::Range;
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+use std::vec;
+
+use super::parser;
+use super::scan::unescape;
+use super::scan::{skip_string, skip_value, skip_whitespace};
+use super::types::{JsonObject, JsonValue};
+
+// documents smaller than this are not worth the thread overhead
+const MIN_PARALLEL_LEN: usize = 64 * 1024;
+const MIN_CHUNK_LEN: usize = 16 * 1024;
+
+/// How a value found by the pre-scan is split into tasks. Containers
+/// bigger than a chunk are split between their members or elements, at
+/// any depth, and everything else is left to a single task.
+#[derive(Debug, PartialEq)]
+enum Plan {
+    /// A run of comma separated values that one task parses together.
+    Values(Range<usize>),
+    /// An object, with the range of each key and the plan for its value.
+    Object(Vec<(Range<usize>, Plan)>),
+    /// An array, whose elements are those of each of its parts in turn.
+    Vec(Vec<Plan>),
+}
+
+/// Parses `json_content` using up to `threads` worker threads.
+///
+/// A structural pre-scan splits the top level object into its members,
+/// and large objects and arrays inside it are further split between
+/// their members and elements. Every chunk is lexed and parsed on its
+/// own, and the result is the same `JsonObject` that `rson::parse`
+/// returns for the same input.
+///
+/// The chunks are parsed strictly, so arrays with missing or trailing
+/// commas such as `[1 2]` or `[1,]` fail there. `rson::parse` accepts
+/// them, so when any chunk fails the whole document is parsed again
+/// sequentially, which also reports real errors the way `parse` does.
+pub fn parse_parallel(json_content: String, threads: usize) -> JsonObject {
+    if threads <= 1 || json_content.len() < MIN_PARALLEL_LEN {
+        return crate::parse(json_content);
+    }
+
+    let chunk_len = (json_content.len() / (threads * 4)).max(MIN_CHUNK_LEN);
+    let members = match plan_document(json_content.as_bytes(), chunk_len) {
+        Some(members) => members,
+        // let the sequential parser report whatever is wrong with it
+        None => return crate::parse(json_content),
+    };
+
+    let mut tasks: Vec<&str> = vec![];
+    for (_, plan) in &members {
+        collect_tasks(plan, &json_content, &mut tasks);
+    }
+
+    let mut parsed = match run_pool(&tasks, threads) {
+        Some(parsed) => parsed.into_iter(),
+        None => return crate::parse(json_content),
+    };
+    build_object(&members, &json_content, &mut parsed)
+}
+
+fn collect_tasks<'a>(plan: &Plan, json: &'a str, tasks: &mut Vec<&'a str>) {
+    match plan {
+        Plan::Values(range) => tasks.push(&json[range.clone()]),
+        Plan::Object(members) => members
+            .iter()
+            .for_each(|(_, plan)| collect_tasks(plan, json, tasks)),
+        Plan::Vec(parts) => parts
+            .iter()
+            .for_each(|plan| collect_tasks(plan, json, tasks)),
+    }
+}
+
+/// Puts the values of the tasks in `parsed` together the way they were
+/// split up, taking them in the order `collect_tasks` gave them out.
+fn build(plan: &Plan, json: &str, parsed: &mut vec::IntoIter<Vec<JsonValue>>) -> Vec<JsonValue> {
+    match plan {
+        Plan::Values(_) => parsed.next().unwrap(),
+        Plan::Object(members) => vec![JsonValue::Object(build_object(members, json, parsed))],
+        Plan::Vec(parts) => {
+            let mut elements: Vec<JsonValue> = vec![];
+            for plan in parts {
+                elements.append(&mut build(plan, json, parsed));
+            }
+            vec![JsonValue::Vec(elements.into())]
+        }
+    }
+}
+
+fn build_object(
+    members: &[(Range<usize>, Plan)],
+    json: &str,
+    parsed: &mut vec::IntoIter<Vec<JsonValue>>,
+) -> JsonObject {
+    let mut object = JsonObject::new();
+    for (key, plan) in members {
+        let key = unescape(&json[key.start + 1..key.end - 1]).into_owned();
+        let value = build(plan, json, parsed).pop().unwrap();
+        object.insert(key, value);
+    }
+    object
+}
+
+/// Parses every task as the comma separated contents of an array, on a
+/// pool of `threads` workers pulling tasks off a shared counter. Returns
+/// `None` if any task fails to parse.
+fn run_pool(tasks: &[&str], threads: usize) -> Option<Vec<Vec<JsonValue>>> {
+    let next_task = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let mut results: Vec<Option<Vec<JsonValue>>> = vec![None; tasks.len()];
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(tasks.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let index = next_task.fetch_add(1, Ordering::Relaxed);
+                        if index >= tasks.len() || failed.load(Ordering::Relaxed) {
+                            return done;
+                        }
+                        match parser::parse_elements(tasks[index]) {
+                            Ok(values) => done.push((index, values)),
+                            Err(_) => failed.store(true, Ordering::Relaxed),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            match worker.join() {
+                Ok(done) => done
+                    .into_iter()
+                    .for_each(|(index, values)| results[index] = Some(values)),
+                Err(cause) => panic::resume_unwind(cause),
+            }
+        }
+    });
+
+    results.into_iter().collect()
+}
+
+/// Plans the members of the top level object. Returns `None` if the
+/// document does not have the expected shape.
+fn plan_document(bytes: &[u8], chunk_len: usize) -> Option<Vec<(Range<usize>, Plan)>> {
+    let start = skip_whitespace(bytes, 0);
+    if bytes.get(start) != Some(&b'{') {
+        return None;
+    }
+    let end = skip_value(bytes, start)?;
+    if skip_whitespace(bytes, end) != bytes.len() {
+        return None;
+    }
+    plan_members(bytes, start..end, chunk_len)
+}
+
+fn plan_value(bytes: &[u8], value: Range<usize>, chunk_len: usize) -> Option<Plan> {
+    if value.len() <= chunk_len {
+        return Some(Plan::Values(value));
+    }
+    match bytes[value.start] {
+        b'{' => Some(Plan::Object(plan_members(bytes, value, chunk_len)?)),
+        b'[' => Some(Plan::Vec(plan_elements(bytes, value, chunk_len)?)),
+        _ => Some(Plan::Values(value)),
+    }
+}
+
+/// Splits the object spanning `object` into the ranges of its keys and
+/// the plans for their values.
+fn plan_members(
+    bytes: &[u8],
+    object: Range<usize>,
+    chunk_len: usize,
+) -> Option<Vec<(Range<usize>, Plan)>> {
+    let content_end = object.end - 1;
+    let mut members = vec![];
+    let mut pos = skip_whitespace(bytes, object.start + 1);
+    if pos == content_end {
+        return Some(members);
+    }
+
+    loop {
+        if bytes.get(pos) != Some(&b'"') {
+            return None;
+        }
+        let key_end = skip_string(bytes, pos)?;
+        let key = pos..key_end;
+
+        pos = skip_whitespace(bytes, key_end);
+        if bytes.get(pos) != Some(&b':') {
+            return None;
+        }
+        let value_start = skip_whitespace(bytes, pos + 1);
+        let value_end = skip_value(bytes, value_start)?;
+        if value_end == value_start {
+            return None;
+        }
+        members.push((key, plan_value(bytes, value_start..value_end, chunk_len)?));
+
+        pos = skip_whitespace(bytes, value_end);
+        match bytes.get(pos) {
+            Some(b',') if pos < content_end => pos = skip_whitespace(bytes, pos + 1),
+            Some(b'}') if pos == content_end => return Some(members),
+            _ => return None,
+        }
+    }
+}
+
+/// Splits the contents of the array spanning `array` into runs of whole
+/// elements of roughly `chunk_len` bytes each, with elements that are
+/// large containers themselves planned on their own.
+fn plan_elements(bytes: &[u8], array: Range<usize>, chunk_len: usize) -> Option<Vec<Plan>> {
+    let content_end = array.end - 1;
+    let mut parts = vec![];
+    let mut run: Option<Range<usize>> = None;
+    let mut pos = skip_whitespace(bytes, array.start + 1);
+    if pos == content_end {
+        return Some(parts);
+    }
+
+    loop {
+        let value_start = pos;
+        let value_end = skip_value(bytes, value_start)?;
+        if value_end == value_start {
+            return None;
+        }
+        pos = skip_whitespace(bytes, value_end);
+        let last = pos == content_end;
+        if !last && bytes.get(pos) != Some(&b',') {
+            return None;
+        }
+
+        if value_end - value_start > chunk_len && matches!(bytes[value_start], b'{' | b'[') {
+            if let Some(run) = run.take() {
+                parts.push(Plan::Values(run));
+            }
+            parts.push(plan_value(bytes, value_start..value_end, chunk_len)?);
+        } else {
+            let current = run.get_or_insert(value_start..value_end);
+            current.end = value_end;
+            if current.len() >= chunk_len {
+                parts.push(Plan::Values(run.take().unwrap()));
+            }
+        }
+
+        if last {
+            if let Some(run) = run {
+                parts.push(Plan::Values(run));
+            }
+            return Some(parts);
+        }
+        pos = skip_whitespace(bytes, pos + 1);
+        if pos == content_end {
+            // a trailing comma, which only the sequential parser accepts
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::large_document;
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let json = large_document();
+        assert!(json.len() > MIN_PARALLEL_LEN);
+
+        let sequential = crate::parse(json.clone());
+        let parallel = parse_parallel(json, 4);
+        assert_eq!(parallel, sequential);
+    }
+
+    #[test]
+    fn plan_splits_nested_containers() {
+        let json = "{\"a\": [1, [2, 3], {\"b\": \"]\"}, 4], \"c\": {\"d\": [5, 6]}}";
+        let members = plan_document(json.as_bytes(), 8).unwrap();
+        let parts: Vec<&str> = members
+            .iter()
+            .flat_map(|(_, plan)| {
+                let mut tasks = vec![];
+                collect_tasks(plan, json, &mut tasks);
+                tasks
+            })
+            .collect();
+        assert_eq!(parts, vec!["1, [2, 3]", "\"]\"", "4", "[5, 6]"]);
+        assert_eq!(
+            members[1].1,
+            Plan::Object(vec![(40..43, Plan::Values(45..51))])
+        );
+    }
+
+    #[test]
+    fn same_result_as_parse() {
+        let nested = format!("{{\"outer\": {}}}", large_document());
+        let mut form_feed = large_document();
+        form_feed.insert(1, '\u{c}');
+        let mut trailing_comma = large_document();
+        trailing_comma.insert(trailing_comma.len() - 1, ',');
+
+        for json in &[large_document(), nested, form_feed, trailing_comma] {
+            let sequential = panic::catch_unwind(|| crate::parse(json.clone()));
+            let parallel = panic::catch_unwind(|| parse_parallel(json.clone(), 4));
+            match (sequential, parallel) {
+                (Ok(sequential), Ok(parallel)) => assert_eq!(parallel, sequential),
+                (Err(_), Err(_)) => {}
+                (sequential, parallel) => panic!(
+                    "parse succeeded: {}, parse_parallel succeeded: {}",
+                    sequential.is_ok(),
+                    parallel.is_ok()
+                ),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Lexer doesn't understand value: =")]
+    fn parallel_panics_like_sequential() {
+        let mut json = large_document();
+        json.insert_str(1, "\"key\"= 1,");
+        parse_parallel(json, 4);
+    }
+
+    #[test]
+    fn accepts_what_parse_accepts() {
+        let lenient = ["[1 2]", "[1,]", "[[1 2], {\"a\": [3,]}]"];
+        for array in &lenient {
+            let mut json = large_document();
+            json.insert_str(1, &format!("\"lenient\": {},", array));
+
+            let sequential = crate::parse(json.clone());
+            let parallel = parse_parallel(json, 4);
+            assert_eq!(parallel, sequential, "{}", array);
+            assert!(parallel.get_value("lenient").is_some());
+        }
+    }
+}
@@ -1,8 +1,10 @@
+use std::io::Read;
+use std::ops::Range;
 use std::slice::Iter;
 
-use crate::types::strip_quotes;
-
-use super::lexer::{Delimiters, JsonTokenType, Numbers, Token};
+use super::error::Error;
+use super::intern::KeyInterner;
+use super::lexer::{Delimiters, JsonTokenType, Numbers, Token, TokenReader};
 use super::types::{JsonNum, JsonObject, JsonValue};
 
 fn check_colon_delimiter(token: Option<&Token>) {
@@ -37,7 +39,7 @@ fn parse_array(mut token_iter: Iter<Token>) -> (Iter<Token>, JsonValue) {
                 match del {
                     Delimiters::Comma => continue,
                     Delimiters::RightBracket => {
-                        return (token_iter, JsonValue::Vec(vec));
+                        return (token_iter, JsonValue::Vec(vec.into()));
                     }
                     // nested array
                     Delimiters::LeftBracket => {
@@ -70,6 +72,14 @@ pub fn parse_tokens(iter: Iter<Token>) -> (Iter<Token>, JsonObject) {
 
     let mut key: Option<String> = None;
 
+    // empty object
+    if let Some(token) = token_iter.as_slice().first() {
+        if let JsonTokenType::Delimiter(Delimiters::RightBrace) = token.get_token() {
+            token_iter.next();
+            return (token_iter, object);
+        }
+    }
+
     loop {
         if let None = key {
             key = get_key(&token_iter.next());
@@ -181,6 +191,9 @@ pub fn parse(insert_vec: Vec<Token>) -> JsonObject {
     let mut token_vec = insert_vec.clone();
 
     token_vec = remove_first_and_last_brace(token_vec);
+    if token_vec.is_empty() {
+        return JsonObject::new();
+    }
 
     let token_iter = token_vec.iter();
     let (_, json_object) = parse_tokens(token_iter);
@@ -188,11 +201,131 @@ pub fn parse(insert_vec: Vec<Token>) -> JsonObject {
     return json_object;
 }
 
+/// Lexes and parses the comma separated contents of an array, such as
+/// a run of elements cut out of a larger document, as strictly as
+/// `read_value` does.
+pub fn parse_elements(elements: &str) -> Result<Vec<JsonValue>, Error> {
+    let json = format!("[{}]", elements);
+    let mut tokens = TokenReader::new(json.as_bytes());
+    let first = tokens.expect_token()?;
+    match read_value(&mut tokens, first, None)? {
+        JsonValue::Vec(vec) if tokens.next_token()?.is_none() => Ok(vec.into_vec()),
+        _ => Err(Error::syntax("Expected array elements".to_string(), 0)),
+    }
+}
+
+/// Builds the value starting with `first` straight from a token stream,
+/// returning syntax errors instead of panicking. This is the parser behind
+/// every part of the crate that reads a `JsonValue` from a stream or from
+/// part of a document. With an `interner`, the keys of all objects are
+/// shared through it.
+///
+/// It is stricter than `parse`, which skips over commas in arrays rather
+/// than requiring them: `parse` reads both `[1 2]` and `[1,]` as arrays,
+/// while this returns a syntax error for them.
+pub fn read_value<R: Read>(
+    tokens: &mut TokenReader<R>,
+    first: (Token, Range<u64>),
+    mut interner: Option<&mut KeyInterner>,
+) -> Result<JsonValue, Error> {
+    let (token, range) = first;
+    match token.get_token() {
+        JsonTokenType::Delimiter(Delimiters::LeftBrace) => {
+            Ok(JsonValue::Object(read_object(tokens, interner)?))
+        }
+        JsonTokenType::Delimiter(Delimiters::LeftBracket) => {
+            let mut vec: Vec<JsonValue> = vec![];
+            let mut next = tokens.expect_token()?;
+            if next.0.get_token() == JsonTokenType::Delimiter(Delimiters::RightBracket) {
+                return Ok(JsonValue::Vec(vec.into()));
+            }
+            loop {
+                vec.push(read_value(tokens, next, interner.as_deref_mut())?);
+                let del =
+                    tokens.expect_delimiter(&[Delimiters::Comma, Delimiters::RightBracket])?;
+                if del == Delimiters::RightBracket {
+                    return Ok(JsonValue::Vec(vec.into()));
+                }
+                next = tokens.expect_token()?;
+            }
+        }
+        JsonTokenType::String(string) => Ok(JsonValue::String(string)),
+        JsonTokenType::Number(Numbers::Integer(integer)) => {
+            Ok(JsonValue::Num(JsonNum::Int(integer)))
+        }
+        JsonTokenType::Number(Numbers::Float(float)) => Ok(JsonValue::Num(JsonNum::Float(float))),
+        JsonTokenType::Boolean(bool) => Ok(JsonValue::Bool(bool)),
+        JsonTokenType::Null => Ok(JsonValue::Null),
+        JsonTokenType::Delimiter(del) => Err(Error::syntax(
+            format!("Unexpected token: {:?}", del),
+            range.start,
+        )),
+    }
+}
+
+/// Reads the members of an object whose left brace was just read.
+pub fn read_object<R: Read>(
+    tokens: &mut TokenReader<R>,
+    mut interner: Option<&mut KeyInterner>,
+) -> Result<JsonObject, Error> {
+    let mut object = JsonObject::new();
+    let mut first_member = true;
+    loop {
+        let (token, range) = tokens.expect_token()?;
+        let key = match token.get_token() {
+            JsonTokenType::Delimiter(Delimiters::RightBrace) if first_member => return Ok(object),
+            JsonTokenType::String(key) => key,
+            unexpected => {
+                let message = format!("Expected key to be string, instead got: {:?}", unexpected);
+                return Err(Error::syntax(message, range.start));
+            }
+        };
+        first_member = false;
+
+        tokens.expect_delimiter(&[Delimiters::Colon])?;
+        let next = tokens.expect_token()?;
+        let value = read_value(tokens, next, interner.as_deref_mut())?;
+        match interner.as_deref_mut() {
+            Some(interner) => object.insert_shared(interner.intern(&key), value),
+            None => object.insert(key, value),
+        }
+
+        if tokens.expect_delimiter(&[Delimiters::Comma, Delimiters::RightBrace])?
+            == Delimiters::RightBrace
+        {
+            return Ok(object);
+        }
+    }
+}
+
+/// Reads a document that has to be a single top level object.
+pub fn read_document<R: Read>(
+    tokens: &mut TokenReader<R>,
+    interner: Option<&mut KeyInterner>,
+) -> Result<JsonObject, Error> {
+    let (token, range) = tokens.expect_token()?;
+    if token.get_token() != JsonTokenType::Delimiter(Delimiters::LeftBrace) {
+        let message = format!(
+            "Object does not start with left brace, instead I got: {:?}",
+            token
+        );
+        return Err(Error::syntax(message, range.start));
+    }
+
+    let object = read_object(tokens, interner)?;
+    if let Some((token, range)) = tokens.next_token()? {
+        let message = format!("Unexpected token after end of object: {:?}", token);
+        return Err(Error::syntax(message, range.start));
+    }
+
+    Ok(object)
+}
+
 fn get_key(next_val: &Option<&Token>) -> Option<String> {
     if let Some(next_key) = next_val {
         let key = next_key.get_token();
         if let JsonTokenType::String(val) = key {
-            return Some(strip_quotes(val));
+            return Some(val);
         } else {
             panic!("Expected key to be string, instead got: {:?}", key)
         }
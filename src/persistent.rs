@@ -0,0 +1,749 @@
+//! Immutable values with structural sharing. Cloning is O(1), and an
+//! update copies only the trie nodes on the path from the root to the
+//! changed value, O(log n) of them, sharing everything else with the
+//! original. Values are `Send + Sync`, so snapshots can be handed to other
+//! threads.
+//!
+//! Arrays are `PersistentVec`s, tries of 32 wide nodes indexed by position.
+//! Objects are `PersistentObject`s, which keep their members in insertion
+//! order in a `PersistentVec` and find them through a hash array mapped
+//! trie of their keys.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::iter::FromIterator;
+use std::sync::Arc;
+
+use super::pointer;
+use super::types::{JsonNum, JsonObject, JsonValue};
+
+/// The bits of an index or hash used at each level of a trie.
+const BITS: u32 = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+enum Trie<T> {
+    Branch(Vec<Arc<Trie<T>>>),
+    Leaf(Vec<T>),
+}
+
+/// A persistent vector. Every node but the rightmost on each level is
+/// full, so the position of a value spells out the path to it, `BITS` bits
+/// per level.
+pub struct PersistentVec<T> {
+    len: usize,
+    /// How far to shift an index for the child of the root to take.
+    shift: u32,
+    root: Arc<Trie<T>>,
+}
+
+impl<T> PersistentVec<T> {
+    pub fn new() -> PersistentVec<T> {
+        PersistentVec {
+            len: 0,
+            shift: 0,
+            root: Arc::new(Trie::Leaf(vec![])),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        self.leaf(index).first()
+    }
+
+    /// The values of the leaf holding `index`, from `index` on.
+    fn leaf(&self, index: usize) -> &[T] {
+        let mut node = &*self.root;
+        let mut shift = self.shift;
+        loop {
+            match node {
+                Trie::Branch(children) => {
+                    node = &children[(index >> shift) & MASK];
+                    shift -= BITS;
+                }
+                Trie::Leaf(values) => return &values[index & MASK..],
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            vec: self,
+            index: 0,
+            leaf: &[],
+        }
+    }
+
+    /// Whether `other` is a clone of this vector that has not been changed
+    /// since, so that the two share all their nodes.
+    pub fn ptr_eq(&self, other: &PersistentVec<T>) -> bool {
+        Arc::ptr_eq(&self.root, &other.root)
+    }
+}
+
+impl<T: Clone> PersistentVec<T> {
+    /// A copy with the value at `index` replaced. Panics if `index` is out
+    /// of bounds.
+    pub fn set(&self, index: usize, value: T) -> PersistentVec<T> {
+        assert!(index < self.len, "Index {} out of bounds", index);
+        PersistentVec {
+            root: Arc::new(set_in(&self.root, self.shift, index, value)),
+            ..self.clone()
+        }
+    }
+
+    /// A copy with `value` added at the end.
+    pub fn push(&self, value: T) -> PersistentVec<T> {
+        if self.len == WIDTH << self.shift {
+            // the root is full, so the tree grows a level
+            let root = Trie::Branch(vec![self.root.clone(), Arc::new(path(self.shift, value))]);
+            return PersistentVec {
+                len: self.len + 1,
+                shift: self.shift + BITS,
+                root: Arc::new(root),
+            };
+        }
+        PersistentVec {
+            len: self.len + 1,
+            shift: self.shift,
+            root: Arc::new(push_in(&self.root, self.shift, self.len, value)),
+        }
+    }
+}
+
+fn set_in<T: Clone>(node: &Trie<T>, shift: u32, index: usize, value: T) -> Trie<T> {
+    match node {
+        Trie::Branch(children) => {
+            let mut children = children.clone();
+            let child = (index >> shift) & MASK;
+            children[child] = Arc::new(set_in(&children[child], shift - BITS, index, value));
+            Trie::Branch(children)
+        }
+        Trie::Leaf(values) => {
+            let mut values = values.clone();
+            values[index & MASK] = value;
+            Trie::Leaf(values)
+        }
+    }
+}
+
+fn push_in<T: Clone>(node: &Trie<T>, shift: u32, index: usize, value: T) -> Trie<T> {
+    match node {
+        Trie::Branch(children) => {
+            let mut children = children.clone();
+            let child = (index >> shift) & MASK;
+            if child < children.len() {
+                children[child] = Arc::new(push_in(&children[child], shift - BITS, index, value));
+            } else {
+                children.push(Arc::new(path(shift - BITS, value)));
+            }
+            Trie::Branch(children)
+        }
+        Trie::Leaf(values) => {
+            let mut values = values.clone();
+            values.push(value);
+            Trie::Leaf(values)
+        }
+    }
+}
+
+/// A new branch down to a leaf holding only `value`.
+fn path<T>(shift: u32, value: T) -> Trie<T> {
+    if shift == 0 {
+        Trie::Leaf(vec![value])
+    } else {
+        Trie::Branch(vec![Arc::new(path(shift - BITS, value))])
+    }
+}
+
+impl<T> Clone for PersistentVec<T> {
+    fn clone(&self) -> PersistentVec<T> {
+        PersistentVec {
+            len: self.len,
+            shift: self.shift,
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<T> Default for PersistentVec<T> {
+    fn default() -> PersistentVec<T> {
+        PersistentVec::new()
+    }
+}
+
+impl<T> FromIterator<T> for PersistentVec<T> {
+    /// Builds the trie bottom up, filling one leaf after the other.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> PersistentVec<T> {
+        let mut values = iter.into_iter();
+        let mut nodes = vec![];
+        let mut len = 0;
+        loop {
+            let leaf: Vec<T> = values.by_ref().take(WIDTH).collect();
+            if leaf.is_empty() {
+                break;
+            }
+            len += leaf.len();
+            nodes.push(Trie::Leaf(leaf));
+        }
+
+        let mut shift = 0;
+        while nodes.len() > 1 {
+            let mut children = nodes.into_iter().map(Arc::new);
+            nodes = vec![];
+            loop {
+                let branch: Vec<_> = children.by_ref().take(WIDTH).collect();
+                if branch.is_empty() {
+                    break;
+                }
+                nodes.push(Trie::Branch(branch));
+            }
+            shift += BITS;
+        }
+
+        match nodes.pop() {
+            Some(root) => PersistentVec {
+                len,
+                shift,
+                root: Arc::new(root),
+            },
+            None => PersistentVec::new(),
+        }
+    }
+}
+
+impl<T: PartialEq> PartialEq for PersistentVec<T> {
+    fn eq(&self, other: &PersistentVec<T>) -> bool {
+        self.ptr_eq(other) || (self.len == other.len && self.iter().eq(other.iter()))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PersistentVec<T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Iterator over the values of a `PersistentVec`, a leaf at a time.
+pub struct Iter<'a, T> {
+    vec: &'a PersistentVec<T>,
+    index: usize,
+    /// The rest of the current leaf.
+    leaf: &'a [T],
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.leaf.is_empty() {
+            if self.index >= self.vec.len {
+                return None;
+            }
+            self.leaf = self.vec.leaf(self.index);
+        }
+        let (value, rest) = self.leaf.split_first()?;
+        self.leaf = rest;
+        self.index += 1;
+        Some(value)
+    }
+}
+
+/// A slot of a `KeyNode`.
+#[derive(Clone)]
+enum KeySlot {
+    /// A key with its position among the members.
+    Key {
+        hash: u64,
+        key: Arc<str>,
+        position: usize,
+    },
+    Node(Arc<KeyNode>),
+    /// Keys whose hashes are equal in all 64 bits.
+    Collision {
+        hash: u64,
+        keys: Vec<(Arc<str>, usize)>,
+    },
+}
+
+/// A node of a hash array mapped trie, with a slot for every bit set in
+/// `bitmap`, in order.
+#[derive(Clone, Default)]
+struct KeyNode {
+    bitmap: u32,
+    slots: Vec<KeySlot>,
+}
+
+impl KeyNode {
+    /// The bit for `hash` on the level at `shift`.
+    fn bit(hash: u64, shift: u32) -> u32 {
+        1 << ((hash >> shift) as usize & MASK)
+    }
+
+    fn slot(&self, bit: u32) -> usize {
+        (self.bitmap & (bit - 1)).count_ones() as usize
+    }
+
+    fn get(&self, hash: u64, key: &str, shift: u32) -> Option<usize> {
+        let bit = KeyNode::bit(hash, shift);
+        if self.bitmap & bit == 0 {
+            return None;
+        }
+        match &self.slots[self.slot(bit)] {
+            KeySlot::Key {
+                key: slot_key,
+                position,
+                ..
+            } => Some(*position).filter(|_| **slot_key == *key),
+            KeySlot::Node(node) => node.get(hash, key, shift + BITS),
+            KeySlot::Collision { keys, .. } => keys
+                .iter()
+                .find(|(slot_key, _)| **slot_key == *key)
+                .map(|(_, position)| *position),
+        }
+    }
+
+    /// A copy with `key`, which must not be in it yet, added at `position`.
+    fn insert(&self, hash: u64, key: Arc<str>, position: usize, shift: u32) -> KeyNode {
+        let bit = KeyNode::bit(hash, shift);
+        let index = self.slot(bit);
+        let mut node = self.clone();
+        if self.bitmap & bit == 0 {
+            node.bitmap |= bit;
+            node.slots.insert(
+                index,
+                KeySlot::Key {
+                    hash,
+                    key,
+                    position,
+                },
+            );
+            return node;
+        }
+
+        node.slots[index] = match &self.slots[index] {
+            KeySlot::Node(child) => {
+                KeySlot::Node(Arc::new(child.insert(hash, key, position, shift + BITS)))
+            }
+            KeySlot::Key {
+                hash: other,
+                key: other_key,
+                position: other_position,
+            } if *other == hash => KeySlot::Collision {
+                hash,
+                keys: vec![(other_key.clone(), *other_position), (key, position)],
+            },
+            KeySlot::Collision { hash: other, keys } if *other == hash => {
+                let mut keys = keys.clone();
+                keys.push((key, position));
+                KeySlot::Collision { hash, keys }
+            }
+            // the hashes differ, and so must split up on a lower level
+            KeySlot::Key { hash: other, .. } | KeySlot::Collision { hash: other, .. } => {
+                let child = KeyNode {
+                    bitmap: KeyNode::bit(*other, shift + BITS),
+                    slots: vec![self.slots[index].clone()],
+                };
+                KeySlot::Node(Arc::new(child.insert(hash, key, position, shift + BITS)))
+            }
+        };
+        node
+    }
+}
+
+/// A persistent object. Members keep the order they were first inserted
+/// in, and are found by key in O(log n).
+#[derive(Clone)]
+pub struct PersistentObject {
+    members: PersistentVec<(Arc<str>, PersistentValue)>,
+    keys: Arc<KeyNode>,
+    hasher: RandomState,
+}
+
+impl PersistentObject {
+    pub fn new() -> PersistentObject {
+        PersistentObject {
+            members: PersistentVec::new(),
+            keys: Arc::new(KeyNode::default()),
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    fn hash(&self, key: &str) -> u64 {
+        let mut hasher = self.hasher.build_hasher();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.keys.get(self.hash(key), key, 0)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&PersistentValue> {
+        let position = self.position(key)?;
+        self.members.get(position).map(|(_, value)| value)
+    }
+
+    /// A copy with `key` set to `value`. A new key goes after the others.
+    pub fn insert(&self, key: &str, value: PersistentValue) -> PersistentObject {
+        if let Some(position) = self.position(key) {
+            let key = self.members.get(position).unwrap().0.clone();
+            return PersistentObject {
+                members: self.members.set(position, (key, value)),
+                ..self.clone()
+            };
+        }
+
+        let key: Arc<str> = Arc::from(key);
+        let keys = self
+            .keys
+            .insert(self.hash(&key), key.clone(), self.len(), 0);
+        PersistentObject {
+            members: self.members.push((key, value)),
+            keys: Arc::new(keys),
+            hasher: self.hasher.clone(),
+        }
+    }
+
+    /// The members in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &PersistentValue)> {
+        self.members.iter().map(|(key, value)| (&**key, value))
+    }
+
+    /// Whether `other` is a clone of this object that has not been changed
+    /// since.
+    pub fn ptr_eq(&self, other: &PersistentObject) -> bool {
+        self.members.ptr_eq(&other.members)
+    }
+}
+
+impl Default for PersistentObject {
+    fn default() -> PersistentObject {
+        PersistentObject::new()
+    }
+}
+
+/// Objects are equal if they have the same members, in any order, like
+/// `JsonObject`s.
+impl PartialEq for PersistentObject {
+    fn eq(&self, other: &PersistentObject) -> bool {
+        self.ptr_eq(other)
+            || (self.len() == other.len()
+                && self
+                    .iter()
+                    .all(|(key, value)| other.get(key) == Some(value)))
+    }
+}
+
+impl fmt::Debug for PersistentObject {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PersistentValue {
+    String(Arc<str>),
+    Num(JsonNum),
+    Bool(bool),
+    Null,
+    Vec(PersistentVec<PersistentValue>),
+    Object(PersistentObject),
+}
+
+impl PersistentValue {
+    pub fn from_value(value: &JsonValue) -> PersistentValue {
+        match value {
+            JsonValue::String(string) => PersistentValue::String(Arc::from(string.as_str())),
+            JsonValue::Num(num) => PersistentValue::Num(*num),
+            JsonValue::Bool(bool) => PersistentValue::Bool(*bool),
+            JsonValue::Null => PersistentValue::Null,
+            JsonValue::Vec(vec) => {
+                PersistentValue::Vec(vec.iter().map(PersistentValue::from_value).collect())
+            }
+            JsonValue::Object(object) => {
+                let mut members = PersistentObject::new();
+                for (key, value) in object.to_iter() {
+                    members = members.insert(key, PersistentValue::from_value(value));
+                }
+                PersistentValue::Object(members)
+            }
+        }
+    }
+
+    pub fn to_value(&self) -> JsonValue {
+        match self {
+            PersistentValue::String(string) => JsonValue::String(string.to_string()),
+            PersistentValue::Num(num) => JsonValue::Num(*num),
+            PersistentValue::Bool(bool) => JsonValue::Bool(*bool),
+            PersistentValue::Null => JsonValue::Null,
+            PersistentValue::Vec(vec) => {
+                JsonValue::Vec(vec.iter().map(PersistentValue::to_value).collect())
+            }
+            PersistentValue::Object(members) => {
+                let mut object = JsonObject::new();
+                for (key, value) in members.iter() {
+                    object.insert(key.to_string(), value.to_value());
+                }
+                JsonValue::Object(object)
+            }
+        }
+    }
+
+    /// The value of `key` if this is an object that has it.
+    pub fn get_value(&self, key: &str) -> Option<&PersistentValue> {
+        match self {
+            PersistentValue::Object(members) => members.get(key),
+            _ => None,
+        }
+    }
+
+    /// The child a pointer token refers to, a key or an array index.
+    fn child(&self, token: &str) -> Option<&PersistentValue> {
+        match self {
+            PersistentValue::Vec(vec) => token.parse::<usize>().ok().and_then(|i| vec.get(i)),
+            _ => self.get_value(token),
+        }
+    }
+
+    /// The value at a JSON Pointer such as `/server/ports/0`.
+    pub fn get_in(&self, pointer: &str) -> Option<&PersistentValue> {
+        pointer::split(pointer)
+            .ok()?
+            .iter()
+            .try_fold(self, |value, token| value.child(token))
+    }
+
+    /// Returns a copy with the value at `pointer` set to `value`, sharing
+    /// everything off that path with `self`. Only the trie nodes leading
+    /// to the changed value are copied, in every array and object on the
+    /// path.
+    ///
+    /// The last token may name a new key of an object, or one past the end
+    /// of an array (or `-`) to append. Returns `None` if the path leads
+    /// anywhere else, or isn't a valid pointer; the empty pointer replaces
+    /// the whole value.
+    pub fn set_in(&self, pointer: &str, value: PersistentValue) -> Option<PersistentValue> {
+        self.set_tokens(&pointer::split(pointer).ok()?, value)
+    }
+
+    fn set_tokens(&self, tokens: &[String], value: PersistentValue) -> Option<PersistentValue> {
+        let (token, rest) = match tokens.split_first() {
+            Some(split) => split,
+            None => return Some(value),
+        };
+
+        match self {
+            PersistentValue::Vec(vec) => {
+                let index = match token.as_str() {
+                    "-" => vec.len(),
+                    index => index.parse::<usize>().ok()?,
+                };
+                let vec = match vec.get(index) {
+                    Some(child) => vec.set(index, child.set_tokens(rest, value)?),
+                    None if index == vec.len() && rest.is_empty() => vec.push(value),
+                    None => return None,
+                };
+                Some(PersistentValue::Vec(vec))
+            }
+            PersistentValue::Object(members) => {
+                let value = match members.get(token) {
+                    Some(child) => child.set_tokens(rest, value)?,
+                    None if rest.is_empty() => value,
+                    None => return None,
+                };
+                Some(PersistentValue::Object(members.insert(token, value)))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn config() -> PersistentValue {
+        let json = "{\"server\": {\"ports\": [80, 443], \"host\": \"a\", \"tls\": {}}, \
+                    \"users\": [{\"name\": \"x\"}]}";
+        PersistentValue::from_value(&JsonValue::Object(crate::parse(json.to_string())))
+    }
+
+    fn shared(first: &PersistentValue, second: &PersistentValue) -> bool {
+        match (first, second) {
+            (PersistentValue::Vec(first), PersistentValue::Vec(second)) => first.ptr_eq(second),
+            (PersistentValue::Object(first), PersistentValue::Object(second)) => {
+                first.ptr_eq(second)
+            }
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn set_in_copies_only_the_path() {
+        let old = config();
+        let new = old
+            .set_in("/server/ports/1", PersistentValue::Num(JsonNum::Int(8443)))
+            .unwrap();
+
+        assert_eq!(
+            old.get_in("/server/ports/1"),
+            Some(&PersistentValue::Num(JsonNum::Int(443)))
+        );
+        assert_eq!(
+            new.get_in("/server/ports/1"),
+            Some(&PersistentValue::Num(JsonNum::Int(8443)))
+        );
+        // every container on the path is copied, at both levels
+        assert!(!shared(&old, &new));
+        for path in &["/server", "/server/ports"] {
+            assert!(!shared(
+                old.get_in(path).unwrap(),
+                new.get_in(path).unwrap()
+            ));
+        }
+        // and everything beside it is shared, at both levels
+        for path in &["/users", "/users/0", "/server/tls"] {
+            assert!(shared(old.get_in(path).unwrap(), new.get_in(path).unwrap()));
+        }
+    }
+
+    #[test]
+    fn set_in_adds_and_rejects() {
+        let value = config();
+        let added = value
+            .set_in("/server/secure", PersistentValue::Bool(true))
+            .unwrap()
+            .set_in("/users/-", PersistentValue::Null)
+            .unwrap();
+        assert_eq!(
+            added.get_in("/server/secure"),
+            Some(&PersistentValue::Bool(true))
+        );
+        assert_eq!(added.get_in("/users/1"), Some(&PersistentValue::Null));
+
+        assert!(value
+            .set_in("/missing/key", PersistentValue::Null)
+            .is_none());
+        assert!(value.set_in("/users/5", PersistentValue::Null).is_none());
+        assert!(value
+            .set_in("/server/host/x", PersistentValue::Null)
+            .is_none());
+        assert_eq!(
+            value.set_in("", PersistentValue::Null),
+            Some(PersistentValue::Null)
+        );
+    }
+
+    #[test]
+    fn vec_matches_std_vec() {
+        let mut expected: Vec<usize> = (0..1000).collect();
+        let mut vec: PersistentVec<usize> = expected.iter().copied().collect();
+        for i in 1000..1100 {
+            vec = vec.push(i);
+            expected.push(i);
+        }
+        for i in (0..1100).step_by(7) {
+            vec = vec.set(i, i * 2);
+            expected[i] = i * 2;
+        }
+
+        assert_eq!(vec.len(), 1100);
+        assert!(vec.iter().eq(expected.iter()));
+        assert_eq!(vec.get(1098), Some(&1098));
+        assert_eq!(vec.get(1100), None);
+        let pushed = (0..1100).fold(PersistentVec::new(), |vec, i| vec.push(expected[i]));
+        assert_eq!(pushed, vec);
+    }
+
+    #[test]
+    fn vec_set_copies_one_path() {
+        let vec: PersistentVec<usize> = (0..WIDTH * WIDTH).collect();
+        let changed = vec.set(WIDTH * WIDTH - 1, 0);
+        match (&*vec.root, &*changed.root) {
+            (Trie::Branch(old), Trie::Branch(new)) => {
+                assert!(Arc::ptr_eq(&old[0], &new[0]));
+                assert!(!Arc::ptr_eq(&old[WIDTH - 1], &new[WIDTH - 1]));
+            }
+            _ => panic!("Expected a branch at the root"),
+        }
+    }
+
+    #[test]
+    fn object_finds_many_keys() {
+        let mut object = PersistentObject::new();
+        for i in 0..500 {
+            object = object.insert(&format!("key_{}", i), PersistentValue::Num(JsonNum::Int(i)));
+        }
+        let updated = object.insert("key_250", PersistentValue::Null);
+
+        assert_eq!(updated.len(), 500);
+        assert_eq!(updated.get("key_250"), Some(&PersistentValue::Null));
+        assert_eq!(
+            object.get("key_250"),
+            Some(&PersistentValue::Num(JsonNum::Int(250)))
+        );
+        assert_eq!(updated.get("key_500"), None);
+        let keys: Vec<&str> = updated.iter().map(|(key, _)| key).take(2).collect();
+        assert_eq!(keys, ["key_0", "key_1"]);
+    }
+
+    #[test]
+    fn key_trie_splits_and_collides() {
+        let deep = 1 << 60;
+        let keys = KeyNode::default()
+            .insert(7, "a".into(), 0, 0)
+            .insert(7, "b".into(), 1, 0)
+            .insert(7 | deep, "c".into(), 2, 0)
+            .insert(8, "d".into(), 3, 0);
+
+        assert_eq!(keys.get(7, "a", 0), Some(0));
+        assert_eq!(keys.get(7, "b", 0), Some(1));
+        assert_eq!(keys.get(7 | deep, "c", 0), Some(2));
+        assert_eq!(keys.get(8, "d", 0), Some(3));
+        assert_eq!(keys.get(7, "c", 0), None);
+        assert_eq!(keys.get(7 | deep, "a", 0), None);
+    }
+
+    #[test]
+    fn equality_ignores_member_order() {
+        let ab = PersistentValue::from_value(&JsonValue::Object(crate::parse(
+            "{\"a\": 1, \"b\": [true]}".to_string(),
+        )));
+        let ba = PersistentValue::from_value(&JsonValue::Object(crate::parse(
+            "{\"b\": [true], \"a\": 1}".to_string(),
+        )));
+        assert_eq!(ab, ba);
+        assert_ne!(ab, ab.set_in("/b/0", PersistentValue::Bool(false)).unwrap());
+    }
+
+    #[test]
+    fn shared_across_threads() {
+        let value = config();
+        let snapshot = value.clone();
+        assert!(shared(&value, &snapshot));
+
+        let handle = thread::spawn(move || snapshot.to_value());
+        assert_eq!(handle.join().unwrap(), value.to_value());
+    }
+}
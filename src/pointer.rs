@@ -0,0 +1,60 @@
+//! JSON Pointers (RFC 6901), such as `/dependencies/eslint` or `/files/0`.
+
+use std::borrow::Cow;
+
+use super::error::Error;
+
+/// Escapes a key so it can be used as one reference token of a pointer.
+pub fn escape_token(token: &str) -> Cow<'_, str> {
+    if !token.contains(['~', '/']) {
+        return Cow::Borrowed(token);
+    }
+    Cow::Owned(token.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Splits a pointer into its unescaped reference tokens. The empty
+/// pointer refers to the whole document and has no tokens.
+pub fn split(pointer: &str) -> Result<Vec<String>, Error> {
+    if pointer.is_empty() {
+        return Ok(vec![]);
+    }
+    if !pointer.starts_with('/') {
+        return Err(Error::InvalidPointer(pointer.to_string()));
+    }
+
+    Ok(pointer[1..]
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+/// Joins unescaped reference tokens into a pointer.
+pub fn join<S: AsRef<str>>(tokens: &[S]) -> String {
+    let mut pointer = String::new();
+    for token in tokens {
+        pointer.push('/');
+        pointer.push_str(&escape_token(token.as_ref()));
+    }
+    pointer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_and_join() {
+        let tokens = split("/a~1b/m~0n/0").unwrap();
+        assert_eq!(tokens, vec!["a/b", "m~n", "0"]);
+        assert_eq!(join(&tokens), "/a~1b/m~0n/0");
+        assert!(split("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reject_relative_pointer() {
+        match split("a/b") {
+            Err(Error::InvalidPointer(pointer)) => assert_eq!(pointer, "a/b"),
+            other => panic!("Expected invalid pointer, got {:?}", other),
+        }
+    }
+}
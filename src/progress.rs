@@ -0,0 +1,153 @@
+//! Parsing with progress reporting and cooperative cancellation.
+
+use std::error;
+use std::fmt;
+use std::io::{self, Read};
+
+use super::error::Error;
+use super::lexer::TokenReader;
+use super::parser;
+use super::types::JsonObject;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    pub bytes_read: u64,
+    /// The size of the input, if it is known up front.
+    pub total_bytes: Option<u64>,
+}
+
+/// Returned by progress callbacks to tell the parser whether to go on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Control {
+    Continue,
+    Cancel,
+}
+
+/// Carried through the reader as an `io::Error` so the parser stops at
+/// the next read, then turned into `Error::Cancelled`.
+#[derive(Debug)]
+struct CancelRequested;
+
+impl fmt::Display for CancelRequested {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("Parsing was cancelled")
+    }
+}
+
+impl error::Error for CancelRequested {}
+
+/// Wraps a reader and reports every chunk read from it to the callback.
+struct ProgressReader<R: Read, F: FnMut(Progress) -> Control> {
+    reader: R,
+    on_progress: F,
+    bytes_read: u64,
+    total_bytes: Option<u64>,
+}
+
+impl<R: Read, F: FnMut(Progress) -> Control> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        self.bytes_read += len as u64;
+
+        let progress = Progress {
+            bytes_read: self.bytes_read,
+            total_bytes: self.total_bytes,
+        };
+        match (self.on_progress)(progress) {
+            Control::Continue => Ok(len),
+            Control::Cancel => Err(io::Error::new(io::ErrorKind::Other, CancelRequested)),
+        }
+    }
+}
+
+/// Like `rson::parse`, but calls `on_progress` as the input is consumed.
+/// If the callback returns `Control::Cancel`, parsing stops and
+/// `Error::Cancelled` is returned.
+pub fn parse_with_progress<F>(json_content: String, on_progress: F) -> Result<JsonObject, Error>
+where
+    F: FnMut(Progress) -> Control,
+{
+    let total_bytes = json_content.len() as u64;
+    parse_reader_with_progress(json_content.as_bytes(), Some(total_bytes), on_progress)
+}
+
+/// Parses a top level object from `reader`, calling `on_progress` after
+/// every chunk read, which is at most 64 KiB.
+pub fn parse_reader_with_progress<R, F>(
+    reader: R,
+    total_bytes: Option<u64>,
+    on_progress: F,
+) -> Result<JsonObject, Error>
+where
+    R: Read,
+    F: FnMut(Progress) -> Control,
+{
+    let mut tokens = TokenReader::new(ProgressReader {
+        reader,
+        on_progress,
+        bytes_read: 0,
+        total_bytes,
+    });
+
+    parser::read_document(&mut tokens, None).map_err(|err| match err {
+        Error::Io(io_err) if is_cancel(&io_err) => Error::Cancelled,
+        err => err,
+    })
+}
+
+fn is_cancel(err: &io::Error) -> bool {
+    err.get_ref()
+        .map_or(false, |inner| inner.is::<CancelRequested>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::large_document;
+    use std::fs;
+
+    #[test]
+    fn reports_progress_until_done() {
+        let json = fs::read_to_string("./test_files/test_package.json").unwrap();
+        let mut reports: Vec<Progress> = vec![];
+        let object = parse_with_progress(json.clone(), |progress| {
+            reports.push(progress);
+            Control::Continue
+        })
+        .unwrap();
+
+        assert_eq!(object, crate::parse(json.clone()));
+        let last = reports.last().unwrap();
+        assert_eq!(last.bytes_read, json.len() as u64);
+        assert_eq!(last.total_bytes, Some(json.len() as u64));
+    }
+
+    #[test]
+    fn cancels_promptly() {
+        let json = large_document();
+        let mut calls = 0;
+        let result = parse_with_progress(json, |progress| {
+            calls += 1;
+            if progress.bytes_read > 0 {
+                Control::Cancel
+            } else {
+                Control::Continue
+            }
+        });
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn unknown_total() {
+        let json = "{\"key\": [1, 2]}";
+        let mut total = Some(0);
+        parse_reader_with_progress(json.as_bytes(), None, |progress| {
+            total = progress.total_bytes;
+            Control::Continue
+        })
+        .unwrap();
+        assert_eq!(total, None);
+    }
+}
@@ -0,0 +1,282 @@
+//! Streaming reformatting, mapping tokens straight to a `JsonWriter`
+//! without building a DOM, so files of any size can be minified or pretty
+//! printed in constant memory.
+
+use std::io::{Read, Write};
+
+use super::error::Error;
+use super::lexer::{Delimiters, JsonTokenType, Token, TokenReader};
+use super::scan::is_number;
+use super::serializer::PrettyConfig;
+use super::writer::JsonWriter;
+
+/// What the grammar allows as the next token.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Expect {
+    Value,
+    /// Right after `[`.
+    ValueOrEnd,
+    Key,
+    /// Right after `{`.
+    KeyOrEnd,
+    Colon,
+    CommaOrEnd,
+    /// The top level value is complete.
+    Done,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Container {
+    Array,
+    Object,
+}
+
+/// Rewrites the JSON document read from `reader` with the layout of
+/// `writer` and returns the underlying writer.
+///
+/// The document is validated in the same pass and the first error is
+/// returned with its byte offset. Everything before the error has already
+/// been written by then. Numbers are copied as they are written, so
+/// `1.50` stays `1.50` and integers of any size pass through.
+pub fn reformat<R: Read, W: Write>(reader: R, writer: JsonWriter<W>) -> Result<W, Error> {
+    let mut reformatter = Reformatter {
+        tokens: TokenReader::new(reader).allow_big_integers(),
+        writer,
+        stack: vec![],
+        expect: Expect::Value,
+    };
+    reformatter.run()?;
+
+    let offset = reformatter.tokens.offset();
+    reformatter
+        .writer
+        .finish()
+        .map_err(|err| writer_error(err, offset))
+}
+
+/// Rewrites the document without any whitespace. Like `JsonWriter`, this
+/// writes in small pieces, so `writer` should be buffered.
+pub fn minify<R: Read, W: Write>(reader: R, writer: W) -> Result<W, Error> {
+    reformat(reader, JsonWriter::new(writer))
+}
+
+/// Rewrites the document laid out as set up by `config`.
+pub fn prettify<R: Read, W: Write>(reader: R, writer: W, config: PrettyConfig) -> Result<W, Error> {
+    reformat(reader, JsonWriter::pretty(writer, config))
+}
+
+fn writer_error(err: Error, offset: u64) -> Error {
+    match err {
+        Error::Io(err) => Error::Io(err),
+        // the grammar is checked before anything reaches the writer
+        other => Error::syntax(other.to_string(), offset),
+    }
+}
+
+struct Reformatter<R: Read, W: Write> {
+    tokens: TokenReader<R>,
+    writer: JsonWriter<W>,
+    stack: Vec<Container>,
+    expect: Expect,
+}
+
+impl<R: Read, W: Write> Reformatter<R, W> {
+    fn run(&mut self) -> Result<(), Error> {
+        loop {
+            let (token, range) = match self.tokens.next_token()? {
+                Some(next) => next,
+                None if self.expect == Expect::Done => return Ok(()),
+                None => {
+                    let message = "Unexpected end of input".to_string();
+                    return Err(Error::syntax(message, self.tokens.offset()));
+                }
+            };
+
+            if let JsonTokenType::Number(_) = token.get_token() {
+                if !is_number(token.raw()) {
+                    let message = format!("Invalid number: {}", token.raw());
+                    return Err(Error::syntax(message, range.start));
+                }
+            }
+            let written = self.write_token(&token);
+            let token = token.get_token();
+            match written {
+                Ok(true) => {}
+                Ok(false) if self.expect == Expect::Done => {
+                    let message = format!("Unexpected token after end of document: {:?}", token);
+                    return Err(Error::syntax(message, range.start));
+                }
+                Ok(false) => {
+                    let message = format!("Unexpected token: {:?}", token);
+                    return Err(Error::syntax(message, range.start));
+                }
+                Err(err) => return Err(writer_error(err, range.start)),
+            }
+        }
+    }
+
+    /// Writes `token` if the grammar allows it here, returning false if not.
+    fn write_token(&mut self, token: &Token) -> Result<bool, Error> {
+        use Delimiters::*;
+
+        let raw = token.raw();
+        let token = &token.get_token();
+        let expects_value = matches!(self.expect, Expect::Value | Expect::ValueOrEnd);
+        let top = self.stack.last().copied();
+        match (self.expect, token) {
+            (_, JsonTokenType::Delimiter(LeftBrace)) if expects_value => {
+                self.writer.begin_object()?;
+                self.stack.push(Container::Object);
+                self.expect = Expect::KeyOrEnd;
+            }
+            (_, JsonTokenType::Delimiter(LeftBracket)) if expects_value => {
+                self.writer.begin_array()?;
+                self.stack.push(Container::Array);
+                self.expect = Expect::ValueOrEnd;
+            }
+            (Expect::ValueOrEnd, JsonTokenType::Delimiter(RightBracket)) => {
+                self.writer.end_array()?;
+                self.close();
+            }
+            (_, JsonTokenType::Delimiter(_)) if expects_value => return Ok(false),
+            (_, scalar) if expects_value => {
+                self.write_scalar(scalar, raw)?;
+                self.end_value();
+            }
+            (Expect::Key, JsonTokenType::String(key))
+            | (Expect::KeyOrEnd, JsonTokenType::String(key)) => {
+                self.writer.key(key)?;
+                self.expect = Expect::Colon;
+            }
+            (Expect::KeyOrEnd, JsonTokenType::Delimiter(RightBrace)) => {
+                self.writer.end_object()?;
+                self.close();
+            }
+            (Expect::Colon, JsonTokenType::Delimiter(Colon)) => self.expect = Expect::Value,
+            (Expect::CommaOrEnd, JsonTokenType::Delimiter(Comma)) => {
+                self.expect = match top {
+                    Some(Container::Object) => Expect::Key,
+                    _ => Expect::Value,
+                };
+            }
+            (Expect::CommaOrEnd, JsonTokenType::Delimiter(RightBrace))
+                if top == Some(Container::Object) =>
+            {
+                self.writer.end_object()?;
+                self.close();
+            }
+            (Expect::CommaOrEnd, JsonTokenType::Delimiter(RightBracket))
+                if top == Some(Container::Array) =>
+            {
+                self.writer.end_array()?;
+                self.close();
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn write_scalar(&mut self, scalar: &JsonTokenType, raw: &str) -> Result<(), Error> {
+        match scalar {
+            JsonTokenType::String(string) => self.writer.string(string),
+            JsonTokenType::Number(_) => self.writer.number(raw),
+            JsonTokenType::Boolean(bool) => self.writer.bool(*bool),
+            JsonTokenType::Null => self.writer.null(),
+            JsonTokenType::Delimiter(_) => unreachable!(),
+        }
+    }
+
+    /// Closes the innermost array or object, which completes a value.
+    fn close(&mut self) {
+        self.stack.pop();
+        self.end_value();
+    }
+
+    /// Moves on after a complete value.
+    fn end_value(&mut self) {
+        self.expect = if self.stack.is_empty() {
+            Expect::Done
+        } else {
+            Expect::CommaOrEnd
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn minified(json: &str) -> Result<String, Error> {
+        minify(json.as_bytes(), vec![]).map(|out| String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn minify_keeps_member_order() {
+        let json = "{ \"b\" : [1, 2.5, \"x\\n\\u0041\"],\n  \"a\": {}, \"c\": [ ] }";
+        assert_eq!(
+            minified(json).unwrap(),
+            "{\"b\":[1,2.5,\"x\\nA\"],\"a\":{},\"c\":[]}"
+        );
+        assert_eq!(minified("  null ").unwrap(), "null");
+    }
+
+    #[test]
+    fn numbers_are_copied_verbatim() {
+        let json = "[1.50, 1e2, -0.0, 2E-3, 123456789012345678901234567890]";
+        assert_eq!(
+            minified(json).unwrap(),
+            "[1.50,1e2,-0.0,2E-3,123456789012345678901234567890]"
+        );
+        for (json, expected) in &[("[01]", 1), ("[1.]", 1), ("[-.5]", 1), ("[1e]", 3)] {
+            match minified(json) {
+                Err(Error::Syntax { offset, .. }) => assert_eq!(offset, *expected, "{}", json),
+                other => panic!("expected syntax error for {}, got {:?}", json, other),
+            }
+        }
+    }
+
+    #[test]
+    fn prettify_within_width() {
+        let json = "{\"short\": [1, 2.50], \"long\": [\"aaaaaaaaaa\", \"bbbbbbbbbb\"]}";
+        let config = PrettyConfig {
+            max_width: Some(30),
+            ..PrettyConfig::default()
+        };
+        let pretty = prettify(json.as_bytes(), vec![], config).unwrap();
+        assert_eq!(
+            String::from_utf8(pretty).unwrap(),
+            "{\n  \"short\": [1, 2.50],\n  \"long\": [\n    \"aaaaaaaaaa\",\n    \"bbbbbbbbbb\"\n  ]\n}"
+        );
+    }
+
+    #[test]
+    fn prettify_test_file() {
+        let json = fs::read_to_string("./test_files/test_package.json").unwrap();
+        let config = PrettyConfig::default();
+        let pretty = prettify(json.as_bytes(), vec![], config).unwrap();
+        let pretty = String::from_utf8(pretty).unwrap();
+
+        assert!(pretty.starts_with("{\n  \""));
+        assert_eq!(crate::parse(pretty), crate::parse(json));
+    }
+
+    #[test]
+    fn reports_first_error() {
+        let cases = [
+            ("{\"a\": [1, 2}", 11),
+            ("{\"a\" 1}", 5),
+            ("[1,]", 3),
+            ("{\"a\": 1,}", 8),
+            ("[1] 2", 4),
+            ("{1: 2}", 1),
+            ("[1, 2", 5),
+        ];
+        for (json, expected) in cases.iter() {
+            match minified(json) {
+                Err(Error::Syntax { offset, .. }) => assert_eq!(offset, *expected, "{}", json),
+                other => panic!("expected syntax error for {}, got {:?}", json, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Byte level helpers for skimming over JSON text without lexing it.
+
+use std::borrow::Cow;
+
+use super::types::JsonNum;
+
+/// Whether `byte` is JSON whitespace. Unlike `u8::is_ascii_whitespace`
+/// this does not include form feed.
+pub fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\n' | b'\r' | b'\t')
+}
+
+pub fn skip_whitespace(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && is_whitespace(bytes[pos]) {
+        pos += 1;
+    }
+    pos
+}
+
+/// Whether `raw` is a number in JSON's grammar, which unlike Rust's
+/// does not allow a leading `+`, leading zeros or a bare `.`.
+pub fn is_number(raw: &str) -> bool {
+    let bytes = raw.as_bytes();
+    let digits = |pos: usize| {
+        bytes[pos.min(bytes.len())..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count()
+    };
+
+    let mut pos = (bytes.first() == Some(&b'-')) as usize;
+    match bytes.get(pos) {
+        Some(b'0') => pos += 1,
+        Some(b'1'..=b'9') => pos += digits(pos),
+        _ => return false,
+    }
+    if bytes.get(pos) == Some(&b'.') {
+        match digits(pos + 1) {
+            0 => return false,
+            count => pos += 1 + count,
+        }
+    }
+    if let Some(b'e') | Some(b'E') = bytes.get(pos) {
+        pos += 1;
+        if let Some(b'+') | Some(b'-') = bytes.get(pos) {
+            pos += 1;
+        }
+        match digits(pos) {
+            0 => return false,
+            count => pos += count,
+        }
+    }
+    pos == bytes.len()
+}
+
+/// Decodes a number already checked by `is_number`, as a float if it has
+/// a fraction or an exponent and as an integer otherwise. `None` if the
+/// integer doesn't fit an `i64`.
+pub fn parse_number(raw: &str) -> Option<JsonNum> {
+    if raw.contains(['.', 'e', 'E']) {
+        raw.parse::<f64>().ok().map(JsonNum::Float)
+    } else {
+        raw.parse::<i64>().ok().map(JsonNum::Int)
+    }
+}
+
+/// Returns the position just after the string starting at `pos`.
+pub fn skip_string(bytes: &[u8], mut pos: usize) -> Option<usize> {
+    pos += 1;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' => pos += 2,
+            b'"' => return Some(pos + 1),
+            _ => pos += 1,
+        }
+    }
+    None
+}
+
+/// Returns the position just after the value starting at `pos`, only
+/// looking at the brackets and quotes that give the document its shape.
+pub fn skip_value(bytes: &[u8], mut pos: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'"' => {
+                pos = skip_string(bytes, pos)?;
+                if depth == 0 {
+                    return Some(pos);
+                }
+                continue;
+            }
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                if depth == 0 {
+                    return Some(pos);
+                }
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos + 1);
+                }
+            }
+            byte if depth == 0 && (byte == b',' || is_whitespace(byte)) => return Some(pos),
+            _ => {}
+        }
+        pos += 1;
+    }
+    if depth == 0 {
+        Some(pos)
+    } else {
+        None
+    }
+}
+
+/// Decodes the escape sequences in the contents of a JSON string, that
+/// is without its surrounding quotes. Only allocates if there are any.
+pub fn unescape(raw: &str) -> Cow<'_, str> {
+    match try_unescape(raw) {
+        Ok(unescaped) => unescaped,
+        Err(message) => panic!("{}", message),
+    }
+}
+
+/// Like `unescape`, but returns invalid escapes as an error message.
+pub fn try_unescape(raw: &str) -> Result<Cow<'_, str>, String> {
+    if !raw.contains('\\') {
+        return Ok(Cow::Borrowed(raw));
+    }
+
+    let mut unescaped = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            unescaped.push(char);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => unescaped.push('"'),
+            Some('\\') => unescaped.push('\\'),
+            Some('/') => unescaped.push('/'),
+            Some('b') => unescaped.push('\u{8}'),
+            Some('f') => unescaped.push('\u{c}'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some('u') => {
+                let high = read_hex(&mut chars)?;
+                let code_point = if (0xD800..0xDC00).contains(&high) {
+                    // surrogate pair, the low half follows as another \u escape
+                    if chars.next() != Some('\\') || chars.next() != Some('u') {
+                        return Err(format!("Unpaired surrogate in string: {}", raw));
+                    }
+                    let low = read_hex(&mut chars)?;
+                    if !(0xDC00..0xE000).contains(&low) {
+                        return Err(format!("Unpaired surrogate in string: {}", raw));
+                    }
+                    0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                } else {
+                    high
+                };
+                match char::from_u32(code_point) {
+                    Some(char) => unescaped.push(char),
+                    None => return Err(format!("Invalid unicode escape in string: {}", raw)),
+                }
+            }
+            unexpected => {
+                return Err(format!(
+                    "Invalid escape sequence in string: \\{:?}",
+                    unexpected
+                ))
+            }
+        }
+    }
+
+    Ok(Cow::Owned(unescaped))
+}
+
+fn read_hex(chars: &mut std::str::Chars) -> Result<u32, String> {
+    let hex: String = chars.take(4).collect();
+    match u32::from_str_radix(&hex, 16) {
+        Ok(value) if hex.len() == 4 => Ok(value),
+        _ => Err(format!("Invalid unicode escape: \\u{}", hex)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_value_stops_after_value() {
+        let json = b"{\"a\": [1, \"}\"]} , 2";
+        assert_eq!(skip_value(json, 0), Some(15));
+        assert_eq!(skip_value(b"1234 ,", 0), Some(4));
+        assert_eq!(skip_value(b"[1, 2", 0), None);
+    }
+
+    #[test]
+    fn number_grammar() {
+        for valid in &["0", "-0", "12", "1.5", "-0.25e10", "1E+2", "3e-7"] {
+            assert!(is_number(valid), "{}", valid);
+        }
+        for invalid in &["", "-", "+1", "01", "1.", ".5", "1e", "1e+", "0x10", "1.5.2"] {
+            assert!(!is_number(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn unescape_borrows_without_escapes() {
+        assert!(matches!(unescape("plain"), Cow::Borrowed("plain")));
+    }
+
+    #[test]
+    fn unescape_decodes_escapes() {
+        assert_eq!(unescape("a\\\"b\\n\\t\\\\"), "a\"b\n\t\\");
+        assert_eq!(unescape("\\u00e5\\ud83d\\ude00"), "å😀");
+    }
+}
@@ -0,0 +1,652 @@
+//! Serializes `JsonValue`s back to JSON text, either compact or pretty.
+
+use super::color::{Role, Theme};
+use super::error::Error;
+use super::types::{JsonNum, JsonObject, JsonValue};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Newline {
+    Lf,
+    CrLf,
+}
+
+impl Newline {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Newline::Lf => "\n",
+            Newline::CrLf => "\r\n",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrettyConfig {
+    /// Repeated once per level of nesting.
+    pub indent: String,
+    pub newline: Newline,
+    /// Whether to put a space between a key's colon and its value.
+    pub space_after_colon: bool,
+    /// Whether to end the output with a newline.
+    pub trailing_newline: bool,
+    /// Whether nested arrays and objects are indented as well, or only the
+    /// outermost one with everything inside its members kept compact.
+    pub recursive: bool,
+    /// Keeps arrays and objects on one line when they fit within this many
+    /// columns, and only breaks them up over several lines when they don't.
+    /// `JsonWriter` holds back up to a line of output to decide this.
+    pub max_width: Option<usize>,
+}
+
+impl Default for PrettyConfig {
+    fn default() -> Self {
+        PrettyConfig {
+            indent: "  ".to_string(),
+            newline: Newline::Lf,
+            space_after_colon: true,
+            trailing_newline: false,
+            recursive: true,
+            max_width: None,
+        }
+    }
+}
+
+/// Serializes `value` without any whitespace. Fails with
+/// `Error::NonFiniteNumber` if it holds a NaN or infinite float.
+pub fn to_string_compact(value: &JsonValue) -> Result<String, Error> {
+    let mut out = String::new();
+    write_compact(&mut out, value)?;
+    Ok(out)
+}
+
+/// Serializes `value` spread over several lines as set up by `config`.
+/// Fails like `to_string_compact`.
+pub fn to_string_pretty(value: &JsonValue, config: &PrettyConfig) -> Result<String, Error> {
+    let mut out = String::new();
+    Pretty {
+        config,
+        theme: None,
+    }
+    .write_document(&mut out, value)?;
+    Ok(out)
+}
+
+pub fn object_to_string_compact(object: &JsonObject) -> Result<String, Error> {
+    let mut out = String::new();
+    write_compact_object(&mut out, object)?;
+    Ok(out)
+}
+
+pub fn object_to_string_pretty(
+    object: &JsonObject,
+    config: &PrettyConfig,
+) -> Result<String, Error> {
+    let mut out = String::new();
+    let pretty = Pretty {
+        config,
+        theme: None,
+    };
+    pretty.write_object(&mut out, object, 0, 0)?;
+    pretty.write_end(&mut out);
+    Ok(out)
+}
+
+/// Writes `string` as a quoted JSON string, escaping quotes, backslashes
+/// and control characters.
+pub fn write_escaped_str(out: &mut String, string: &str) {
+    out.push('"');
+    for char in string.chars() {
+        match char {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            control if (control as u32) < 0x20 => {
+                out.push_str(&format!("\\u{:04x}", control as u32))
+            }
+            other => out.push(other),
+        }
+    }
+    out.push('"');
+}
+
+/// Writes a number so that it reads back as the same variant. Floats
+/// always get a decimal point or exponent. JSON has no NaN or infinity,
+/// so those are an `Error::NonFiniteNumber`.
+pub fn write_num(out: &mut String, num: &JsonNum) -> Result<(), Error> {
+    match num {
+        JsonNum::Int(int) => out.push_str(&int.to_string()),
+        JsonNum::Float(float) if float.is_finite() => out.push_str(&format!("{:?}", float)),
+        JsonNum::Float(float) => return Err(Error::NonFiniteNumber(*float)),
+    }
+    Ok(())
+}
+
+fn write_scalar(out: &mut String, value: &JsonValue) -> Result<(), Error> {
+    match value {
+        JsonValue::String(string) => write_escaped_str(out, string),
+        JsonValue::Num(num) => write_num(out, num)?,
+        JsonValue::Bool(bool) => out.push_str(&bool.to_string()),
+        JsonValue::Null => out.push_str("null"),
+        JsonValue::Vec(_) | JsonValue::Object(_) => unreachable!(),
+    }
+    Ok(())
+}
+
+fn write_compact(out: &mut String, value: &JsonValue) -> Result<(), Error> {
+    match value {
+        JsonValue::Vec(vec) => {
+            out.push('[');
+            for (index, element) in vec.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_compact(out, element)?;
+            }
+            out.push(']');
+            Ok(())
+        }
+        JsonValue::Object(object) => write_compact_object(out, object),
+        scalar => write_scalar(out, scalar),
+    }
+}
+
+fn write_compact_object(out: &mut String, object: &JsonObject) -> Result<(), Error> {
+    out.push('{');
+    for (index, (key, value)) in object.to_iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        write_escaped_str(out, key);
+        out.push(':');
+        write_compact(out, value)?;
+    }
+    out.push('}');
+    Ok(())
+}
+
+/// The layout shared by `to_string_pretty` and `color::to_string_colored`,
+/// which only differ in whether tokens are wrapped in color codes.
+pub(crate) struct Pretty<'a> {
+    pub config: &'a PrettyConfig,
+    pub theme: Option<&'a Theme>,
+}
+
+impl<'a> Pretty<'a> {
+    pub fn write_document(&self, out: &mut String, value: &JsonValue) -> Result<(), Error> {
+        self.write_value(out, value, 0, 0)?;
+        self.write_end(out);
+        Ok(())
+    }
+
+    fn write_end(&self, out: &mut String) {
+        if self.config.trailing_newline {
+            out.push_str(self.config.newline.as_str());
+        }
+    }
+
+    fn paint<T>(&self, out: &mut String, role: Role, write: impl FnOnce(&mut String) -> T) -> T {
+        match self.theme.map(|theme| theme.code(role)) {
+            Some(code) if !code.is_empty() => {
+                out.push_str("\x1b[");
+                out.push_str(code);
+                out.push('m');
+                let written = write(out);
+                out.push_str("\x1b[0m");
+                written
+            }
+            _ => write(out),
+        }
+    }
+
+    fn punctuation(&self, out: &mut String, punctuation: &str) {
+        self.paint(out, Role::Punctuation, |out| out.push_str(punctuation));
+    }
+
+    fn scalar(&self, out: &mut String, value: &JsonValue) -> Result<(), Error> {
+        let role = match value {
+            JsonValue::String(_) => Role::String,
+            JsonValue::Num(_) => Role::Number,
+            JsonValue::Bool(_) => Role::Bool,
+            _ => Role::Null,
+        };
+        self.paint(out, role, |out| write_scalar(out, value))
+    }
+
+    fn key(&self, out: &mut String, key: &str, colon: &str) {
+        self.paint(out, Role::Key, |out| write_escaped_str(out, key));
+        self.punctuation(out, ":");
+        out.push_str(colon);
+    }
+
+    fn newline(&self, out: &mut String, depth: usize) {
+        out.push_str(self.config.newline.as_str());
+        for _ in 0..depth {
+            out.push_str(&self.config.indent);
+        }
+    }
+
+    fn colon(&self) -> &'static str {
+        if self.config.space_after_colon {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    /// Writes a value that will be followed by `trailing` characters on the
+    /// same line, which matters when deciding whether it fits on one line.
+    fn write_value(
+        &self,
+        out: &mut String,
+        value: &JsonValue,
+        depth: usize,
+        trailing: usize,
+    ) -> Result<(), Error> {
+        if depth > 0 && !self.config.recursive {
+            return self.write_inline(out, value, ",", "");
+        }
+
+        match value {
+            JsonValue::Vec(vec) if vec.is_empty() => {
+                self.punctuation(out, "[]");
+                Ok(())
+            }
+            JsonValue::Vec(_)
+                if self.fits_on_line(out, trailing, |budget| self.inline_width(value, budget)) =>
+            {
+                self.write_inline(out, value, ", ", self.colon())
+            }
+            JsonValue::Vec(vec) => {
+                self.punctuation(out, "[");
+                for (index, element) in vec.iter().enumerate() {
+                    if index > 0 {
+                        self.punctuation(out, ",");
+                    }
+                    self.newline(out, depth + 1);
+                    let trailing = if index + 1 < vec.len() { 1 } else { 0 };
+                    self.write_value(out, element, depth + 1, trailing)?;
+                }
+                self.newline(out, depth);
+                self.punctuation(out, "]");
+                Ok(())
+            }
+            JsonValue::Object(object) => self.write_object(out, object, depth, trailing),
+            scalar => self.scalar(out, scalar),
+        }
+    }
+
+    fn write_object(
+        &self,
+        out: &mut String,
+        object: &JsonObject,
+        depth: usize,
+        trailing: usize,
+    ) -> Result<(), Error> {
+        let len = object.get_keys().len();
+        if len == 0 {
+            self.punctuation(out, "{}");
+            return Ok(());
+        }
+        if self.fits_on_line(out, trailing, |budget| {
+            self.object_inline_width(object, budget)
+        }) {
+            return self.write_inline_object(out, object, ", ", self.colon());
+        }
+
+        self.punctuation(out, "{");
+        for (index, (key, value)) in object.to_iter().enumerate() {
+            if index > 0 {
+                self.punctuation(out, ",");
+            }
+            self.newline(out, depth + 1);
+            self.key(out, key, self.colon());
+            let trailing = if index + 1 < len { 1 } else { 0 };
+            self.write_value(out, value, depth + 1, trailing)?;
+        }
+        self.newline(out, depth);
+        self.punctuation(out, "}");
+        Ok(())
+    }
+
+    /// Writes `value` on one line, with `comma` between elements and
+    /// `colon` after the colon of every key.
+    fn write_inline(
+        &self,
+        out: &mut String,
+        value: &JsonValue,
+        comma: &str,
+        colon: &str,
+    ) -> Result<(), Error> {
+        match value {
+            JsonValue::Vec(vec) => {
+                self.punctuation(out, "[");
+                for (index, element) in vec.iter().enumerate() {
+                    if index > 0 {
+                        self.punctuation(out, comma);
+                    }
+                    self.write_inline(out, element, comma, colon)?;
+                }
+                self.punctuation(out, "]");
+                Ok(())
+            }
+            JsonValue::Object(object) => self.write_inline_object(out, object, comma, colon),
+            scalar => self.scalar(out, scalar),
+        }
+    }
+
+    fn write_inline_object(
+        &self,
+        out: &mut String,
+        object: &JsonObject,
+        comma: &str,
+        colon: &str,
+    ) -> Result<(), Error> {
+        self.punctuation(out, "{");
+        for (index, (key, value)) in object.to_iter().enumerate() {
+            if index > 0 {
+                self.punctuation(out, comma);
+            }
+            self.key(out, key, colon);
+            self.write_inline(out, value, comma, colon)?;
+        }
+        self.punctuation(out, "}");
+        Ok(())
+    }
+
+    /// Whether something that `inline_width` measures fits within
+    /// `max_width` from the current column. Always false without a
+    /// `max_width`.
+    fn fits_on_line<F>(&self, out: &str, trailing: usize, inline_width: F) -> bool
+    where
+        F: FnOnce(usize) -> Option<usize>,
+    {
+        let max_width = match self.config.max_width {
+            Some(max_width) => max_width,
+            None => return false,
+        };
+        let line_start = out.rfind('\n').map_or(0, |newline| newline + 1);
+        let column = visible_width(&out[line_start..]);
+
+        match max_width.checked_sub(column + trailing) {
+            Some(budget) => inline_width(budget).is_some(),
+            None => false,
+        }
+    }
+
+    /// The width of `value` written inline, or `None` as soon as it is known
+    /// to be wider than `budget`. Each element is measured against what is
+    /// left of the budget, so large values are not measured in full.
+    fn inline_width(&self, value: &JsonValue, budget: usize) -> Option<usize> {
+        let width = match value {
+            JsonValue::Vec(vec) => {
+                let mut width = 1;
+                for (index, element) in vec.iter().enumerate() {
+                    if index > 0 {
+                        width += 2;
+                    }
+                    // leave room for the closing bracket
+                    width += self.inline_width(element, budget.checked_sub(width + 1)?)?;
+                }
+                width + 1
+            }
+            JsonValue::Object(object) => return self.object_inline_width(object, budget),
+            scalar => {
+                // a scalar that can't be written doesn't fit, and writing it reports why
+                let mut out = String::new();
+                write_scalar(&mut out, scalar).ok()?;
+                out.chars().count()
+            }
+        };
+
+        if width > budget {
+            None
+        } else {
+            Some(width)
+        }
+    }
+
+    fn object_inline_width(&self, object: &JsonObject, budget: usize) -> Option<usize> {
+        let mut width = 1;
+        for (index, (key, value)) in object.to_iter().enumerate() {
+            if index > 0 {
+                width += 2;
+            }
+            let mut escaped_key = String::new();
+            write_escaped_str(&mut escaped_key, key);
+            width += escaped_key.chars().count() + 1 + self.colon().len();
+            width += self.inline_width(value, budget.checked_sub(width + 1)?)?;
+        }
+        width += 1;
+
+        if width > budget {
+            None
+        } else {
+            Some(width)
+        }
+    }
+}
+
+/// The number of characters in `line` once color codes are left out.
+fn visible_width(line: &str) -> usize {
+    let mut width = 0;
+    let mut chars = line.chars();
+    while let Some(char) = chars.next() {
+        if char == '\x1b' {
+            chars.by_ref().find(|&char| char == 'm');
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_files;
+    use std::fs;
+
+    fn reparse(json: String) -> JsonValue {
+        JsonValue::Object(crate::parse(json))
+    }
+
+    #[test]
+    fn escapes_strings_and_keys() {
+        let mut object = JsonObject::new();
+        object.insert(
+            "quote\"key".to_string(),
+            JsonValue::String("line\nbreak \"quoted\" \\ \u{1}".to_string()),
+        );
+        let value = JsonValue::Object(object);
+
+        let compact = to_string_compact(&value).unwrap();
+        assert_eq!(
+            compact,
+            "{\"quote\\\"key\":\"line\\nbreak \\\"quoted\\\" \\\\ \\u0001\"}"
+        );
+        assert_eq!(reparse(compact), value);
+    }
+
+    #[test]
+    fn strings_are_decoded_once() {
+        let json = "{\"a\\\"b\": \"tab\\t \\u00e9 \\/\", \"empty\": {}}".to_string();
+        let value = reparse(json);
+        let object = value.as_object().unwrap();
+        assert_eq!(
+            object.get_value("a\"b"),
+            Some(&JsonValue::String("tab\t \u{e9} /".to_string()))
+        );
+
+        // escaping on the way out is what makes decoding on the way in
+        // round trip, rather than escaping the escapes again
+        let compact = to_string_compact(&value).unwrap();
+        assert_eq!(compact, "{\"a\\\"b\":\"tab\\t \u{e9} /\",\"empty\":{}}");
+        assert_eq!(reparse(compact.clone()), value);
+        assert_eq!(
+            to_string_compact(&reparse(compact.clone())).unwrap(),
+            compact
+        );
+    }
+
+    #[test]
+    fn floats_read_back_as_floats() {
+        let vec = JsonValue::from(vec![
+            JsonValue::Num(JsonNum::Float(12.0)),
+            JsonValue::Num(JsonNum::Float(1e300)),
+            JsonValue::Num(JsonNum::Float(-2.5e-7)),
+            JsonValue::Num(JsonNum::Int(-3)),
+        ]);
+        assert_eq!(to_string_compact(&vec).unwrap(), "[12.0,1e300,-2.5e-7,-3]");
+
+        let mut object = JsonObject::new();
+        object.insert("floats".to_string(), vec);
+        let value = JsonValue::Object(object);
+        assert_eq!(reparse(to_string_compact(&value).unwrap()), value);
+    }
+
+    #[test]
+    fn non_finite_floats_are_errors() {
+        let config = PrettyConfig {
+            max_width: Some(80),
+            ..PrettyConfig::default()
+        };
+        for float in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let mut object = JsonObject::new();
+            object.insert(
+                "list".to_string(),
+                JsonValue::from(vec![JsonValue::Num(JsonNum::Float(*float))]),
+            );
+            let value = JsonValue::Object(object);
+
+            assert!(matches!(
+                to_string_compact(&value),
+                Err(Error::NonFiniteNumber(_))
+            ));
+            assert!(matches!(
+                to_string_pretty(&value, &config),
+                Err(Error::NonFiniteNumber(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn pretty_indents_every_level() {
+        let value = reparse("{\"a\": [1, {\"b\": null}], \"c\": {}}".to_string());
+        let config = PrettyConfig {
+            indent: "\t".to_string(),
+            newline: Newline::CrLf,
+            trailing_newline: true,
+            ..PrettyConfig::default()
+        };
+
+        let pretty = to_string_pretty(&value, &config).unwrap();
+        assert!(pretty.contains("\"a\": [\r\n\t\t1,\r\n\t\t{\r\n\t\t\t\"b\": null\r\n\t\t}\r\n\t]"));
+        assert!(pretty.ends_with("}\r\n"));
+        assert_eq!(reparse(pretty), value);
+    }
+
+    #[test]
+    fn pretty_only_first_level() {
+        let value = reparse("{\"a\": [1, 2]}".to_string());
+        let config = PrettyConfig {
+            space_after_colon: false,
+            recursive: false,
+            ..PrettyConfig::default()
+        };
+        assert_eq!(
+            to_string_pretty(&value, &config).unwrap(),
+            "{\n  \"a\":[1,2]\n}"
+        );
+    }
+
+    #[test]
+    fn pretty_within_width() {
+        let value = reparse(
+            "{\"list\": [[1, 2, 3], [\"aaaaaaaaaa\", \"bbbbbbbbbb\", \"cccccccccc\"], {\"nested\": {\"key\": \"value\"}}]}"
+                .to_string(),
+        );
+        let config = PrettyConfig {
+            max_width: Some(36),
+            ..PrettyConfig::default()
+        };
+
+        let pretty = to_string_pretty(&value, &config).unwrap();
+        let expected = "{
+  \"list\": [
+    [1, 2, 3],
+    [
+      \"aaaaaaaaaa\",
+      \"bbbbbbbbbb\",
+      \"cccccccccc\"
+    ],
+    {\"nested\": {\"key\": \"value\"}}
+  ]
+}";
+        assert_eq!(pretty, expected);
+        assert!(pretty.lines().all(|line| line.chars().count() <= 36));
+        assert_eq!(
+            to_string_pretty(&reparse(pretty.clone()), &config).unwrap(),
+            pretty
+        );
+    }
+
+    #[test]
+    fn inline_width_stops_at_budget() {
+        let json = "{\"a\": [1, {\"b\": \"cc\"}], \"d\": []}";
+        let value = reparse(json.to_string());
+        let config = PrettyConfig::default();
+        let pretty = Pretty {
+            config: &config,
+            theme: None,
+        };
+
+        for budget in 0..json.len() + 2 {
+            let expected = Some(json.len()).filter(|&width| width <= budget);
+            assert_eq!(pretty.inline_width(&value, budget), expected);
+        }
+        let array = value.as_object().unwrap().get_value("a").unwrap();
+        assert_eq!(pretty.inline_width(array, 15), None);
+        assert_eq!(pretty.inline_width(array, 16), Some(16));
+    }
+
+    #[test]
+    fn pretty_fits_whole_document() {
+        let value = reparse("{\"a\": [1, {}]}".to_string());
+        let config = PrettyConfig {
+            max_width: Some(80),
+            ..PrettyConfig::default()
+        };
+        assert_eq!(
+            to_string_pretty(&value, &config).unwrap(),
+            "{\"a\": [1, {}]}"
+        );
+    }
+
+    #[test]
+    fn round_trips_test_files() {
+        for (file, json) in test_files() {
+            let value = reparse(json);
+            let pretty = to_string_pretty(&value, &PrettyConfig::default()).unwrap();
+            assert_eq!(reparse(pretty), value, "{}", file);
+            assert_eq!(
+                reparse(to_string_compact(&value).unwrap()),
+                value,
+                "{}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_member_order() {
+        let json = fs::read_to_string("./test_files/test_package.json").unwrap();
+        let minified = crate::reformat::minify(json.as_bytes(), vec![]).unwrap();
+        assert_eq!(
+            to_string_compact(&reparse(json)).unwrap(),
+            String::from_utf8(minified).unwrap()
+        );
+    }
+}
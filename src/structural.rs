@@ -0,0 +1,380 @@
+//! Two stage parsing in the style of simdjson.
+//!
+//! Stage one walks the input eight bytes at a time and uses bit tricks to
+//! classify 64 byte blocks into bitmasks of quotes, backslashes, structural
+//! characters and whitespace. From those it works out which bytes are
+//! inside strings and records the positions of every structural character,
+//! every quote and the first byte of every other scalar. Stage two builds
+//! the DOM by walking those positions, without ever looking at the bytes
+//! in between them apart from string and number contents.
+
+use super::error::Error;
+use super::scan::{is_number, try_unescape};
+use super::types::{JsonNum, JsonObject, JsonValue};
+
+const BLOCK_LEN: usize = 64;
+const ONES: u64 = 0x0101_0101_0101_0101;
+const LOW_SEVEN_BITS: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+
+/// The bit for every byte in `word` that is equal to `byte`.
+fn eq_mask(word: u64, byte: u8) -> u64 {
+    let x = word ^ (ONES * byte as u64);
+    // the high bit of every zero byte, without carries between bytes
+    let zero_bytes = !(((x & LOW_SEVEN_BITS) + LOW_SEVEN_BITS) | x | LOW_SEVEN_BITS);
+    // gather the high bits into the top byte, then move them down
+    (zero_bytes >> 7).wrapping_mul(0x0102_0408_1020_4080) >> 56
+}
+
+/// Sets every bit from a set bit up to, but not including, the next one.
+fn prefix_xor(mut mask: u64) -> u64 {
+    mask ^= mask << 1;
+    mask ^= mask << 2;
+    mask ^= mask << 4;
+    mask ^= mask << 8;
+    mask ^= mask << 16;
+    mask ^= mask << 32;
+    mask
+}
+
+struct BlockMasks {
+    quote: u64,
+    backslash: u64,
+    structural: u64,
+    whitespace: u64,
+}
+
+impl BlockMasks {
+    fn new(block: &[u8; BLOCK_LEN]) -> BlockMasks {
+        let mut masks = BlockMasks {
+            quote: 0,
+            backslash: 0,
+            structural: 0,
+            whitespace: 0,
+        };
+
+        for (index, chunk) in block.chunks_exact(8).enumerate() {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(chunk);
+            let word = u64::from_le_bytes(bytes);
+            let shift = index * 8;
+
+            masks.quote |= eq_mask(word, b'"') << shift;
+            masks.backslash |= eq_mask(word, b'\\') << shift;
+            masks.structural |= (eq_mask(word, b'{')
+                | eq_mask(word, b'}')
+                | eq_mask(word, b'[')
+                | eq_mask(word, b']')
+                | eq_mask(word, b':')
+                | eq_mask(word, b','))
+                << shift;
+            masks.whitespace |= (eq_mask(word, b' ')
+                | eq_mask(word, b'\n')
+                | eq_mask(word, b'\r')
+                | eq_mask(word, b'\t'))
+                << shift;
+        }
+
+        masks
+    }
+}
+
+/// The positions of all structural characters, quotes and scalar starts.
+#[derive(Debug)]
+pub struct StructuralIndex {
+    positions: Vec<u32>,
+}
+
+impl StructuralIndex {
+    pub fn new(json: &str) -> Result<StructuralIndex, Error> {
+        let bytes = json.as_bytes();
+        if bytes.len() >= u32::MAX as usize {
+            let message = "Input too large for a structural index".to_string();
+            return Err(Error::syntax(message, u32::MAX as u64));
+        }
+
+        let mut positions: Vec<u32> = Vec::with_capacity(bytes.len() / 4);
+        // state carried over from one block to the next
+        let mut prev_escaped = false;
+        let mut prev_in_string = 0u64;
+        let mut prev_scalar = 0u64;
+
+        for (block_index, chunk) in bytes.chunks(BLOCK_LEN).enumerate() {
+            // the last block is padded with whitespace
+            let mut block = [b' '; BLOCK_LEN];
+            block[..chunk.len()].copy_from_slice(chunk);
+            let masks = BlockMasks::new(&block);
+
+            let escaped = escaped_mask(masks.backslash, &mut prev_escaped);
+            let quotes = masks.quote & !escaped;
+            let in_string = prefix_xor(quotes) ^ prev_in_string;
+            prev_in_string = 0u64.wrapping_sub(in_string >> 63);
+
+            let outside = !in_string & !quotes;
+            let scalar = outside & !masks.structural & !masks.whitespace;
+            let scalar_starts = scalar & !((scalar << 1) | prev_scalar);
+            prev_scalar = scalar >> 63;
+
+            let mut structurals = (masks.structural & outside) | quotes | scalar_starts;
+            let block_start = (block_index * BLOCK_LEN) as u32;
+            while structurals != 0 {
+                positions.push(block_start + structurals.trailing_zeros());
+                structurals &= structurals - 1;
+            }
+        }
+
+        if prev_in_string != 0 {
+            let message = "Unexpected end of input, string not complete".to_string();
+            return Err(Error::syntax(message, bytes.len() as u64));
+        }
+
+        Ok(StructuralIndex { positions })
+    }
+
+    pub fn positions(&self) -> &[u32] {
+        &self.positions
+    }
+}
+
+/// The bit for every byte that is escaped by a preceding backslash.
+fn escaped_mask(backslash: u64, prev_escaped: &mut bool) -> u64 {
+    let mut escaped = *prev_escaped as u64;
+    *prev_escaped = false;
+
+    // a backslash that is itself escaped does not escape the next byte
+    let mut escapes = backslash & !escaped;
+    while escapes != 0 {
+        let escape = escapes & escapes.wrapping_neg();
+        if escape == 1 << 63 {
+            *prev_escaped = true;
+        }
+        escaped |= escape << 1;
+        escapes &= !(escape | escape << 1);
+    }
+
+    escaped
+}
+
+/// Builds the DOM by walking the structural positions of the input.
+struct DomBuilder<'a> {
+    json: &'a str,
+    positions: &'a [u32],
+    next: usize,
+}
+
+impl<'a> DomBuilder<'a> {
+    fn peek_byte(&self) -> Option<u8> {
+        self.positions
+            .get(self.next)
+            .map(|&pos| self.json.as_bytes()[pos as usize])
+    }
+
+    fn error(&self, message: String, pos: usize) -> Error {
+        Error::syntax(message, pos as u64)
+    }
+
+    fn advance(&mut self) -> Result<usize, Error> {
+        match self.positions.get(self.next) {
+            Some(&pos) => {
+                self.next += 1;
+                Ok(pos as usize)
+            }
+            None => Err(self.error("Unexpected end of input".to_string(), self.json.len())),
+        }
+    }
+
+    fn expect(&mut self, expected: u8, message: &str) -> Result<(), Error> {
+        let pos = self.advance()?;
+        let byte = self.json.as_bytes()[pos];
+        if byte != expected {
+            let message = format!("{}, instead got: {}", message, byte as char);
+            return Err(self.error(message, pos));
+        }
+        Ok(())
+    }
+
+    /// The decoded contents of the string whose opening quote was just
+    /// consumed.
+    fn string_contents(&mut self, open: usize) -> Result<String, Error> {
+        let close = self.advance()?;
+        match try_unescape(&self.json[open + 1..close]) {
+            Ok(contents) => Ok(contents.into_owned()),
+            Err(message) => Err(self.error(message, open)),
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue, Error> {
+        let pos = self.advance()?;
+        match self.json.as_bytes()[pos] {
+            b'{' => Ok(JsonValue::Object(self.object()?)),
+            b'[' => self.array(),
+            b'"' => Ok(JsonValue::String(self.string_contents(pos)?)),
+            byte @ b'}' | byte @ b']' | byte @ b':' | byte @ b',' => {
+                Err(self.error(format!("Unexpected token: {}", byte as char), pos))
+            }
+            _ => self.scalar(pos),
+        }
+    }
+
+    fn object(&mut self) -> Result<JsonObject, Error> {
+        let mut object = JsonObject::new();
+        if self.peek_byte() == Some(b'}') {
+            self.advance()?;
+            return Ok(object);
+        }
+
+        loop {
+            let key_pos = self.advance()?;
+            let byte = self.json.as_bytes()[key_pos];
+            if byte != b'"' {
+                let message = format!("Expected key to be string, instead got: {}", byte as char);
+                return Err(self.error(message, key_pos));
+            }
+            let key = self.string_contents(key_pos)?;
+            self.expect(b':', "Did not get colon after key")?;
+            let value = self.value()?;
+            object.insert(key, value);
+
+            let pos = self.advance()?;
+            match self.json.as_bytes()[pos] {
+                b',' => {}
+                b'}' => return Ok(object),
+                _ => {
+                    let message = "Key value pair did not end in comma or end of object";
+                    return Err(self.error(message.to_string(), pos));
+                }
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<JsonValue, Error> {
+        let mut vec: Vec<JsonValue> = vec![];
+        if self.peek_byte() == Some(b']') {
+            self.advance()?;
+            return Ok(JsonValue::Vec(vec.into()));
+        }
+
+        loop {
+            vec.push(self.value()?);
+            let pos = self.advance()?;
+            match self.json.as_bytes()[pos] {
+                b',' => {}
+                b']' => return Ok(JsonValue::Vec(vec.into())),
+                _ => return Err(self.error("Unexpected end of array".to_string(), pos)),
+            }
+        }
+    }
+
+    fn scalar(&mut self, pos: usize) -> Result<JsonValue, Error> {
+        let end = match self.positions.get(self.next) {
+            Some(&next) => next as usize,
+            None => self.json.len(),
+        };
+        let raw = self.json[pos..end].trim_end_matches([' ', '\n', '\r', '\t']);
+
+        match raw {
+            "true" => Ok(JsonValue::Bool(true)),
+            "false" => Ok(JsonValue::Bool(false)),
+            "null" => Ok(JsonValue::Null),
+            num if !is_number(num) => {
+                Err(self.error(format!("Lexer doesn't understand value: {}", num), pos))
+            }
+            num if num.contains(['.', 'e', 'E']) => match num.parse::<f64>() {
+                Ok(float) => Ok(JsonValue::Num(JsonNum::Float(float))),
+                Err(e) => {
+                    let message = format!("Something went wrong when parsing number: {:?}", e);
+                    Err(self.error(message, pos))
+                }
+            },
+            num => match num.parse::<i64>() {
+                Ok(integer) => Ok(JsonValue::Num(JsonNum::Int(integer))),
+                Err(_) => Err(self.error(format!("Incorrect token found: {}", num), pos)),
+            },
+        }
+    }
+}
+
+/// Parses a top level object with the structural index fast path.
+pub fn parse(json: &str) -> Result<JsonObject, Error> {
+    let index = StructuralIndex::new(json)?;
+    let mut builder = DomBuilder {
+        json,
+        positions: index.positions(),
+        next: 0,
+    };
+
+    builder.expect(b'{', "Object does not start with left brace")?;
+    let object = builder.object()?;
+    if let Some(&pos) = builder.positions.get(builder.next) {
+        let message = "Unexpected input after end of object".to_string();
+        return Err(builder.error(message, pos as usize));
+    }
+
+    Ok(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_files;
+
+    #[test]
+    fn eq_mask_finds_every_byte() {
+        let word = u64::from_le_bytes(*b"a\"b\"\"cd\"");
+        assert_eq!(eq_mask(word, b'"'), 0b1001_1010);
+        assert_eq!(eq_mask(word, b'x'), 0);
+    }
+
+    #[test]
+    fn index_skips_string_contents() {
+        let json = "{\"a\\\"{\": [12, true]}";
+        let index = StructuralIndex::new(json).unwrap();
+        let found: Vec<char> = index
+            .positions()
+            .iter()
+            .map(|&pos| json.as_bytes()[pos as usize] as char)
+            .collect();
+        assert_eq!(
+            found,
+            vec!['{', '"', '"', ':', '[', '1', ',', 't', ']', '}']
+        );
+    }
+
+    #[test]
+    fn escapes_across_block_boundary() {
+        // the backslash is the last byte of the first block
+        let mut json = String::from("{\"");
+        json.push_str(&"x".repeat(BLOCK_LEN - 3));
+        json.push_str("\\\"\": 1}");
+        let object = parse(&json).unwrap();
+        assert_eq!(object.get_keys().len(), 1);
+    }
+
+    #[test]
+    fn matches_sequential_parse() {
+        for (file, json) in test_files() {
+            assert_eq!(
+                parse(&json).unwrap(),
+                crate::parse(json.clone()),
+                "{}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let malformed = [
+            ("{\"key\": \"value}", 15),
+            ("{\"a\": [1 2]}", 9),
+            ("{\"a\": 01}", 6),
+            ("{\"a\": 1,}", 8),
+            ("{\"a\": 1} 2", 9),
+        ];
+        for (json, expected) in &malformed {
+            match parse(json) {
+                Err(Error::Syntax { offset, .. }) => assert_eq!(offset, *expected, "{}", json),
+                other => panic!("Expected syntax error for {}, got {:?}", json, other),
+            }
+        }
+    }
+}
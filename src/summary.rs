@@ -0,0 +1,197 @@
+//! Short one line renderings of values for logs, with long strings, arrays
+//! and objects cut off and replaced by markers saying how much was left out.
+//! NaN and infinite floats, which have no JSON form, are shown as Rust
+//! prints them.
+
+use super::serializer::{write_escaped_str, write_num};
+use super::types::{JsonNum, JsonObject, JsonValue};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SummaryConfig {
+    /// Characters of a string shown before the rest is elided.
+    pub max_string_len: usize,
+    pub max_array_items: usize,
+    pub max_object_keys: usize,
+    /// Arrays and objects nested deeper than this are shown only as a count
+    /// of what they contain.
+    pub max_depth: usize,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        SummaryConfig {
+            max_string_len: 64,
+            max_array_items: 5,
+            max_object_keys: 10,
+            max_depth: 3,
+        }
+    }
+}
+
+/// Renders `value` on one line within the limits of `config`. Elided content
+/// is replaced by markers like `"abc…(+3.2 KB)"`, `[1, 2, … 997 more]` or
+/// `{… 4 keys}`, so the output is only valid JSON if nothing was elided.
+pub fn to_string_summary(value: &JsonValue, config: &SummaryConfig) -> String {
+    let mut out = String::new();
+    write_summary(&mut out, value, config, 0);
+    out
+}
+
+fn write_summary(out: &mut String, value: &JsonValue, config: &SummaryConfig, depth: usize) {
+    match value {
+        JsonValue::String(string) => write_string(out, string, config),
+        JsonValue::Num(JsonNum::Float(float)) if !float.is_finite() => {
+            out.push_str(&float.to_string())
+        }
+        JsonValue::Num(num) => write_num(out, num).unwrap(),
+        JsonValue::Bool(bool) => out.push_str(&bool.to_string()),
+        JsonValue::Null => out.push_str("null"),
+        JsonValue::Vec(vec) if vec.is_empty() => out.push_str("[]"),
+        JsonValue::Vec(vec) if depth >= config.max_depth => {
+            out.push_str(&format!("[… {} {}]", vec.len(), plural(vec.len(), "item")))
+        }
+        JsonValue::Vec(vec) => {
+            out.push('[');
+            for (index, element) in vec.iter().take(config.max_array_items).enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                write_summary(out, element, config, depth + 1);
+            }
+            write_more(out, vec.len(), config.max_array_items);
+            out.push(']');
+        }
+        JsonValue::Object(object) => write_object(out, object, config, depth),
+    }
+}
+
+fn write_object(out: &mut String, object: &JsonObject, config: &SummaryConfig, depth: usize) {
+    let len = object.get_keys().len();
+    if len == 0 {
+        return out.push_str("{}");
+    }
+    if depth >= config.max_depth {
+        return out.push_str(&format!("{{… {} {}}}", len, plural(len, "key")));
+    }
+
+    out.push('{');
+    let members = object.to_iter().take(config.max_object_keys);
+    for (index, (key, value)) in members.enumerate() {
+        if index > 0 {
+            out.push_str(", ");
+        }
+        write_string(out, key, config);
+        out.push_str(": ");
+        write_summary(out, value, config, depth + 1);
+    }
+    write_more(out, len, config.max_object_keys);
+    out.push('}');
+}
+
+/// Writes the marker for the elements past the first `shown`, if any.
+fn write_more(out: &mut String, len: usize, shown: usize) {
+    if len > shown {
+        if shown > 0 {
+            out.push_str(", ");
+        }
+        out.push_str(&format!("… {} more", len - shown));
+    }
+}
+
+fn write_string(out: &mut String, string: &str, config: &SummaryConfig) {
+    match string.char_indices().nth(config.max_string_len) {
+        None => write_escaped_str(out, string),
+        Some((cut, _)) => {
+            let elided = format!("{}…(+{})", &string[..cut], format_size(string.len() - cut));
+            write_escaped_str(out, &elided)
+        }
+    }
+}
+
+fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+
+    if bytes < 1024 {
+        return format!("{} {}", bytes, plural(bytes, "byte"));
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        noun.to_string()
+    } else {
+        format!("{}s", noun)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_value(json: &str) -> JsonValue {
+        JsonValue::Object(crate::parse(json.to_string()))
+    }
+
+    #[test]
+    fn within_limits_is_unchanged() {
+        let value = parse_value("{\"a\": [1, \"two\", null, {\"b\": false}]}");
+        assert_eq!(
+            to_string_summary(&value, &SummaryConfig::default()),
+            "{\"a\": [1, \"two\", null, {\"b\": false}]}"
+        );
+    }
+
+    #[test]
+    fn elides_long_strings() {
+        let value = JsonValue::String(format!("abc{}", "x".repeat(3277)));
+        let config = SummaryConfig {
+            max_string_len: 3,
+            ..SummaryConfig::default()
+        };
+        assert_eq!(to_string_summary(&value, &config), "\"abc…(+3.2 KB)\"");
+
+        let value = JsonValue::String("äöüß".to_string());
+        let config = SummaryConfig {
+            max_string_len: 2,
+            ..SummaryConfig::default()
+        };
+        assert_eq!(to_string_summary(&value, &config), "\"äö…(+4 bytes)\"");
+    }
+
+    #[test]
+    fn elides_array_items_and_deep_values() {
+        let items: Vec<JsonValue> = (0..1000)
+            .map(|i| JsonValue::Num(crate::types::JsonNum::Int(i)))
+            .collect();
+        let value = JsonValue::from(vec![
+            JsonValue::from(items),
+            parse_value("{\"a\": {\"b\": 1, \"c\": 2}}"),
+        ]);
+        let config = SummaryConfig {
+            max_array_items: 3,
+            max_depth: 2,
+            ..SummaryConfig::default()
+        };
+        assert_eq!(
+            to_string_summary(&value, &config),
+            "[[0, 1, 2, … 997 more], {\"a\": {… 2 keys}}]"
+        );
+    }
+
+    #[test]
+    fn elides_object_keys() {
+        let value = parse_value("{\"a\": 1, \"b\": 2, \"c\": 3}");
+        let config = SummaryConfig {
+            max_object_keys: 0,
+            ..SummaryConfig::default()
+        };
+        assert_eq!(to_string_summary(&value, &config), "{… 3 more}");
+    }
+}
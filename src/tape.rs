@@ -0,0 +1,508 @@
+//! A flat "tape" representation for read-mostly use. The whole document is
+//! one `Vec<u64>` of tagged entries plus one string buffer, and every
+//! array and object records where it ends, so a value can be skipped over
+//! in O(1) to reach its next sibling.
+//!
+//! Every entry has its tag in the top byte and a payload below it:
+//!
+//! - `n`, `t`, `f`: null, true and false, without payload.
+//! - `l`, `d`: an integer or a float, whose bits are in the next entry.
+//! - `"`: a string or key starting at the payload in the string buffer,
+//!   with its length in the next entry.
+//! - `[`, `{`: the start of an array or object, with the index of the entry
+//!   after its end in the low 32 bits and the number of elements or members
+//!   in the bits above, saturated at `MAX_COUNT`.
+//! - `]`, `}`: the end of an array or object, with the index of its start.
+//!
+//! Object members are a key entry followed by the entries of the value.
+
+use std::io::Read;
+use std::ops::Range;
+
+use super::error::Error;
+use super::lexer::{Delimiters, JsonTokenType, Numbers, Token, TokenReader};
+use super::types::{JsonNum, JsonObject, JsonValue};
+
+const TAG_SHIFT: u32 = 56;
+const PAYLOAD_MASK: u64 = (1 << TAG_SHIFT) - 1;
+const COUNT_SHIFT: u32 = 32;
+const INDEX_MASK: u64 = (1 << COUNT_SHIFT) - 1;
+const MAX_COUNT: u64 = (1 << (TAG_SHIFT - COUNT_SHIFT)) - 1;
+
+const NULL: u8 = b'n';
+const TRUE: u8 = b't';
+const FALSE: u8 = b'f';
+const INT: u8 = b'l';
+const FLOAT: u8 = b'd';
+const STRING: u8 = b'"';
+const ARRAY_START: u8 = b'[';
+const ARRAY_END: u8 = b']';
+const OBJECT_START: u8 = b'{';
+const OBJECT_END: u8 = b'}';
+
+fn entry(tag: u8, payload: u64) -> u64 {
+    ((tag as u64) << TAG_SHIFT) | (payload & PAYLOAD_MASK)
+}
+
+/// The index of an entry as stored in the low 32 bits of a container's
+/// start, or `TooLarge` if it doesn't fit.
+fn entry_index(index: usize) -> Result<u64, Error> {
+    match index as u64 {
+        index if index <= INDEX_MASK => Ok(index),
+        _ => Err(Error::TooLarge),
+    }
+}
+
+/// A document laid out as a tape. Limited to 2^32 entries, beyond which
+/// building one fails with `Error::TooLarge`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tape {
+    entries: Vec<u64>,
+    strings: String,
+}
+
+impl Tape {
+    /// Lays `json` out flat on a tape. Any value may be at the root.
+    pub fn parse(json: &str) -> Result<Tape, Error> {
+        Tape::from_reader(json.as_bytes())
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> Result<Tape, Error> {
+        let mut tokens = TokenReader::new(reader);
+        let mut tape = Tape {
+            entries: vec![],
+            strings: String::new(),
+        };
+
+        let first = tokens.expect_token()?;
+        tape.read_value(&mut tokens, first)?;
+        if let Some((token, range)) = tokens.next_token()? {
+            let message = format!("Unexpected token after end of document: {:?}", token);
+            return Err(Error::syntax(message, range.start));
+        }
+        Ok(tape)
+    }
+
+    /// Lays out a value that is already parsed. Fails like `parse` for
+    /// values that need more than 2^32 entries.
+    pub fn from_value(value: &JsonValue) -> Result<Tape, Error> {
+        let mut tape = Tape {
+            entries: vec![],
+            strings: String::new(),
+        };
+        tape.push_value(value)?;
+        Ok(tape)
+    }
+
+    /// A cursor at the top level value.
+    pub fn cursor(&self) -> Cursor<'_> {
+        Cursor {
+            tape: self,
+            index: 0,
+        }
+    }
+
+    pub fn to_value(&self) -> JsonValue {
+        self.cursor().to_value()
+    }
+
+    /// The number of entries on the tape.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn push_string(&mut self, string: &str) {
+        self.entries.push(entry(STRING, self.strings.len() as u64));
+        self.entries.push(string.len() as u64);
+        self.strings.push_str(string);
+    }
+
+    fn push_num(&mut self, num: JsonNum) {
+        match num {
+            JsonNum::Int(int) => {
+                self.entries.push(entry(INT, 0));
+                self.entries.push(int as u64);
+            }
+            JsonNum::Float(float) => {
+                self.entries.push(entry(FLOAT, 0));
+                self.entries.push(float.to_bits());
+            }
+        }
+    }
+
+    /// Pushes the start of a container, to be patched by `end_container`.
+    fn start_container(&mut self, tag: u8) -> usize {
+        self.entries.push(entry(tag, 0));
+        self.entries.len() - 1
+    }
+
+    fn end_container(
+        &mut self,
+        start: usize,
+        start_tag: u8,
+        end_tag: u8,
+        count: usize,
+    ) -> Result<(), Error> {
+        self.entries.push(entry(end_tag, start as u64));
+        let count = (count as u64).min(MAX_COUNT);
+        let after = entry_index(self.entries.len())?;
+        self.entries[start] = entry(start_tag, (count << COUNT_SHIFT) | after);
+        Ok(())
+    }
+
+    fn push_value(&mut self, value: &JsonValue) -> Result<(), Error> {
+        match value {
+            JsonValue::String(string) => self.push_string(string),
+            JsonValue::Num(num) => self.push_num(*num),
+            JsonValue::Bool(true) => self.entries.push(entry(TRUE, 0)),
+            JsonValue::Bool(false) => self.entries.push(entry(FALSE, 0)),
+            JsonValue::Null => self.entries.push(entry(NULL, 0)),
+            JsonValue::Vec(vec) => {
+                let start = self.start_container(ARRAY_START);
+                for element in vec {
+                    self.push_value(element)?;
+                }
+                self.end_container(start, ARRAY_START, ARRAY_END, vec.len())?;
+            }
+            JsonValue::Object(object) => {
+                let start = self.start_container(OBJECT_START);
+                for (key, value) in object.to_iter() {
+                    self.push_string(key);
+                    self.push_value(value)?;
+                }
+                self.end_container(start, OBJECT_START, OBJECT_END, object.to_iter().len())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_value<R: Read>(
+        &mut self,
+        tokens: &mut TokenReader<R>,
+        first: (Token, Range<u64>),
+    ) -> Result<(), Error> {
+        let (token, range) = first;
+        match token.get_token() {
+            JsonTokenType::Delimiter(Delimiters::LeftBracket) => {
+                let start = self.start_container(ARRAY_START);
+                let mut count = 0;
+                let mut next = tokens.expect_token()?;
+                if next.0.get_token() != JsonTokenType::Delimiter(Delimiters::RightBracket) {
+                    loop {
+                        self.read_value(tokens, next)?;
+                        count += 1;
+                        let del = tokens
+                            .expect_delimiter(&[Delimiters::Comma, Delimiters::RightBracket])?;
+                        if del == Delimiters::RightBracket {
+                            break;
+                        }
+                        next = tokens.expect_token()?;
+                    }
+                }
+                self.end_container(start, ARRAY_START, ARRAY_END, count)?;
+            }
+            JsonTokenType::Delimiter(Delimiters::LeftBrace) => {
+                let start = self.start_container(OBJECT_START);
+                let mut count = 0;
+                loop {
+                    let (token, range) = tokens.expect_token()?;
+                    match token.get_token() {
+                        JsonTokenType::Delimiter(Delimiters::RightBrace) if count == 0 => break,
+                        JsonTokenType::String(key) => self.push_string(&key),
+                        unexpected => {
+                            let message =
+                                format!("Expected key to be string, instead got: {:?}", unexpected);
+                            return Err(Error::syntax(message, range.start));
+                        }
+                    }
+                    tokens.expect_delimiter(&[Delimiters::Colon])?;
+                    let next = tokens.expect_token()?;
+                    self.read_value(tokens, next)?;
+                    count += 1;
+
+                    if tokens.expect_delimiter(&[Delimiters::Comma, Delimiters::RightBrace])?
+                        == Delimiters::RightBrace
+                    {
+                        break;
+                    }
+                }
+                self.end_container(start, OBJECT_START, OBJECT_END, count)?;
+            }
+            JsonTokenType::String(string) => self.push_string(&string),
+            JsonTokenType::Number(Numbers::Integer(int)) => self.push_num(JsonNum::Int(int)),
+            JsonTokenType::Number(Numbers::Float(float)) => self.push_num(JsonNum::Float(float)),
+            JsonTokenType::Boolean(bool) => {
+                self.entries.push(entry(if bool { TRUE } else { FALSE }, 0))
+            }
+            JsonTokenType::Null => self.entries.push(entry(NULL, 0)),
+            JsonTokenType::Delimiter(del) => {
+                let message = format!("Unexpected token: {:?}", del);
+                return Err(Error::syntax(message, range.start));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A position on a tape, at the start of a value.
+#[derive(Clone, Copy, Debug)]
+pub struct Cursor<'a> {
+    tape: &'a Tape,
+    index: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn tag(&self) -> u8 {
+        (self.tape.entries[self.index] >> TAG_SHIFT) as u8
+    }
+
+    fn payload(&self) -> u64 {
+        self.tape.entries[self.index] & PAYLOAD_MASK
+    }
+
+    fn at(&self, index: usize) -> Cursor<'a> {
+        Cursor {
+            tape: self.tape,
+            index,
+        }
+    }
+
+    /// The index of the entry after this value.
+    fn skip(&self) -> usize {
+        match self.tag() {
+            INT | FLOAT | STRING => self.index + 2,
+            ARRAY_START | OBJECT_START => (self.payload() & INDEX_MASK) as usize,
+            _ => self.index + 1,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'a str> {
+        if self.tag() != STRING {
+            return None;
+        }
+        let start = self.payload() as usize;
+        let len = self.tape.entries[self.index + 1] as usize;
+        Some(&self.tape.strings[start..start + len])
+    }
+
+    pub fn as_num(&self) -> Option<JsonNum> {
+        let bits = self.tape.entries.get(self.index + 1).copied();
+        match self.tag() {
+            INT => bits.map(|bits| JsonNum::Int(bits as i64)),
+            FLOAT => bits.map(|bits| JsonNum::Float(f64::from_bits(bits))),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.tag() {
+            TRUE => Some(true),
+            FALSE => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.tag() == NULL
+    }
+
+    pub fn is_vec(&self) -> bool {
+        self.tag() == ARRAY_START
+    }
+
+    pub fn is_object(&self) -> bool {
+        self.tag() == OBJECT_START
+    }
+
+    /// The number of elements or members, or 0 for anything else. O(1)
+    /// unless there are more than `MAX_COUNT`.
+    pub fn len(&self) -> usize {
+        match self.tag() {
+            ARRAY_START => self.count(|cursor| cursor.elements().count()),
+            OBJECT_START => self.count(|cursor| cursor.to_iter().count()),
+            _ => 0,
+        }
+    }
+
+    fn count(&self, count_all: fn(&Cursor<'a>) -> usize) -> usize {
+        let count = self.payload() >> COUNT_SHIFT;
+        if count < MAX_COUNT {
+            count as usize
+        } else {
+            count_all(self)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Walks the entries between an array's start and end, jumping over
+    /// nested containers. Empty unless this entry starts an array.
+    pub fn elements(&self) -> Elements<'a> {
+        let (next, end) = match self.tag() {
+            ARRAY_START => (self.index + 1, self.skip() - 1),
+            _ => (0, 0),
+        };
+        Elements {
+            cursor: self.at(next),
+            end,
+        }
+    }
+
+    /// Walks the key and value entries between an object's start and end.
+    /// Empty unless this entry starts an object.
+    pub fn to_iter(&self) -> Members<'a> {
+        let (next, end) = match self.tag() {
+            OBJECT_START => (self.index + 1, self.skip() - 1),
+            _ => (0, 0),
+        };
+        Members {
+            cursor: self.at(next),
+            end,
+        }
+    }
+
+    /// The element at `index` of an array, skipping the ones before it.
+    pub fn get(&self, index: usize) -> Option<Cursor<'a>> {
+        self.elements().nth(index)
+    }
+
+    /// The value of `key` in an object, skipping the values of other keys.
+    /// If the key is repeated, the last value is the one `to_value` keeps.
+    pub fn get_value(&self, key: &str) -> Option<Cursor<'a>> {
+        self.to_iter()
+            .filter(|(member, _)| *member == key)
+            .last()
+            .map(|(_, value)| value)
+    }
+
+    pub fn to_value(&self) -> JsonValue {
+        match self.tag() {
+            STRING => JsonValue::String(self.as_str().unwrap().to_string()),
+            INT | FLOAT => JsonValue::Num(self.as_num().unwrap()),
+            TRUE => JsonValue::Bool(true),
+            FALSE => JsonValue::Bool(false),
+            ARRAY_START => {
+                JsonValue::Vec(self.elements().map(|cursor| cursor.to_value()).collect())
+            }
+            OBJECT_START => {
+                let mut object = JsonObject::new();
+                for (key, value) in self.to_iter() {
+                    object.insert(key.to_string(), value.to_value());
+                }
+                JsonValue::Object(object)
+            }
+            _ => JsonValue::Null,
+        }
+    }
+}
+
+pub struct Elements<'a> {
+    cursor: Cursor<'a>,
+    /// The index of the end entry of the array.
+    end: usize,
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = Cursor<'a>;
+
+    fn next(&mut self) -> Option<Cursor<'a>> {
+        if self.cursor.index >= self.end {
+            return None;
+        }
+        let element = self.cursor;
+        self.cursor = element.at(element.skip());
+        Some(element)
+    }
+}
+
+pub struct Members<'a> {
+    cursor: Cursor<'a>,
+    /// The index of the end entry of the object.
+    end: usize,
+}
+
+impl<'a> Iterator for Members<'a> {
+    type Item = (&'a str, Cursor<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cursor.index >= self.end {
+            return None;
+        }
+        let key = self.cursor;
+        let value = key.at(key.skip());
+        self.cursor = value.at(value.skip());
+        Some((key.as_str().unwrap(), value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_files;
+
+    #[test]
+    fn navigate_with_cursor() {
+        let json = "{\"skip\": {\"deep\": [[1, 2], {\"x\": null}]}, \"list\": [\"a\", -7, 1.5, true], \"e\": []}";
+        let tape = Tape::parse(json).unwrap();
+        let root = tape.cursor();
+
+        assert!(root.is_object());
+        assert_eq!(root.len(), 3);
+        let list = root.get_value("list").unwrap();
+        assert_eq!(list.len(), 4);
+        assert_eq!(list.get(0).unwrap().as_str(), Some("a"));
+        assert_eq!(list.get(1).unwrap().as_num(), Some(JsonNum::Int(-7)));
+        assert_eq!(list.get(2).unwrap().as_num(), Some(JsonNum::Float(1.5)));
+        assert_eq!(list.get(3).unwrap().as_bool(), Some(true));
+        assert!(list.get(4).is_none());
+        assert!(root.get_value("e").unwrap().is_empty());
+
+        let deep = root.get_value("skip").unwrap().get_value("deep").unwrap();
+        assert!(deep.get(1).unwrap().get_value("x").unwrap().is_null());
+    }
+
+    #[test]
+    fn round_trips_values() {
+        for (file, json) in test_files() {
+            let value = JsonValue::Object(crate::parse(json.clone()));
+            let tape = Tape::parse(&json).unwrap();
+
+            assert_eq!(tape.to_value(), value, "{}", file);
+            assert_eq!(Tape::from_value(&value).unwrap(), tape, "{}", file);
+        }
+    }
+
+    #[test]
+    fn rejects_indexes_past_the_limit() {
+        assert_eq!(entry_index(INDEX_MASK as usize).unwrap(), INDEX_MASK);
+        assert!(matches!(
+            entry_index(INDEX_MASK as usize + 1),
+            Err(Error::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn last_duplicate_key_wins() {
+        let tape = Tape::parse("{\"a\": 1, \"a\": 2}").unwrap();
+        let value = tape.cursor().get_value("a").unwrap();
+        assert_eq!(value.as_num(), Some(JsonNum::Int(2)));
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        assert!(matches!(
+            Tape::parse("[1, 2"),
+            Err(Error::Syntax { offset: 5, .. })
+        ));
+        assert!(matches!(
+            Tape::parse("{\"a\": 1} 2"),
+            Err(Error::Syntax { offset: 9, .. })
+        ));
+    }
+}
@@ -0,0 +1,45 @@
+//! Fixtures shared by the unit tests of several modules.
+
+use std::fs;
+
+use crate::types::{JsonNum, JsonObject, JsonValue};
+
+/// The files under `test_files` that every parser is checked against,
+/// with their contents.
+pub fn test_files() -> Vec<(&'static str, String)> {
+    ["2.json", "array.json", "mixed.json", "test_package.json"]
+        .iter()
+        .map(|&file| {
+            let json = fs::read_to_string(format!("./test_files/{}", file)).unwrap();
+            (file, json)
+        })
+        .collect()
+}
+
+pub fn int(int: i64) -> JsonValue {
+    JsonValue::Num(JsonNum::Int(int))
+}
+
+/// A small object with a string, a number, an array and an empty object.
+pub fn config() -> JsonObject {
+    crate::parse(
+        "{\"name\": \"app\", \"port\": 80, \"hosts\": [\"a\", \"b\"], \"tls\": {}}".to_string(),
+    )
+}
+
+/// A document of a few hundred kilobytes whose strings contain commas,
+/// brackets and escaped quotes.
+pub fn large_document() -> String {
+    let records: Vec<String> = (0..5000)
+        .map(|i| {
+            format!(
+                "{{\"id\": {},\n\"name\": \"record, [{}]\",\n\"tags\": [\"a\", \"b\\\"c\"],\n\"ok\": true}}",
+                i, i
+            )
+        })
+        .collect();
+    format!(
+        "{{\n\"title\": \"big\",\n\"records\": [{}],\n\"count\": 5000,\n\"nothing\": null\n}}",
+        records.join(", ")
+    )
+}
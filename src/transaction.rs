@@ -0,0 +1,413 @@
+//! Batches of edits to a `JsonObject` that are kept or undone as one unit,
+//! and a bounded history of them for undo and redo.
+
+use std::collections::VecDeque;
+
+use super::change::{self, Change};
+use super::error::Error;
+use super::pointer;
+use super::types::{JsonObject, JsonValue};
+
+/// The changes made by one committed transaction, in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangeSet {
+    changes: Vec<Change>,
+}
+
+impl ChangeSet {
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Makes the changes to `object`, which has to be in the state they
+    /// were made from. If it isn't, `object` is left unchanged.
+    pub fn apply(&self, object: &mut JsonObject) -> Result<(), Error> {
+        make_all(object, &self.changes)
+    }
+
+    /// Undoes the changes to `object`, which has to be in the state they
+    /// left it in. If it isn't, `object` is left unchanged.
+    pub fn revert(&self, object: &mut JsonObject) -> Result<(), Error> {
+        let inverses: Vec<Change> = self.changes.iter().rev().map(Change::inverse).collect();
+        make_all(object, &inverses)
+    }
+}
+
+/// Makes `changes` in order. If one of them fails, the ones before it are
+/// undone again and its error is returned. The undoing goes through the
+/// same checks, and if one of those fails instead, its error is returned.
+fn make_all(object: &mut JsonObject, changes: &[Change]) -> Result<(), Error> {
+    for (made, change) in changes.iter().enumerate() {
+        if let Err(err) = change::apply(object, change) {
+            undo(object, &changes[..made])?;
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Undoes `changes`, the last one first, stopping at the first that fails.
+fn undo(object: &mut JsonObject, changes: &[Change]) -> Result<(), Error> {
+    changes
+        .iter()
+        .rev()
+        .try_for_each(|change| change::apply(object, &change.inverse()))
+}
+
+/// Edits an object, recording every change. The edits are visible right
+/// away, and are undone again by `rollback` or by dropping the transaction
+/// without calling `commit`.
+pub struct Transaction<'a> {
+    object: &'a mut JsonObject,
+    changes: Vec<Change>,
+    done: bool,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new(object: &'a mut JsonObject) -> Transaction<'a> {
+        Transaction {
+            object,
+            changes: vec![],
+            done: false,
+        }
+    }
+
+    /// The object with the edits made so far.
+    pub fn object(&self) -> &JsonObject {
+        self.object
+    }
+
+    fn make(&mut self, change: Change) -> Result<(), Error> {
+        change::apply(self.object, &change)?;
+        self.changes.push(change);
+        Ok(())
+    }
+
+    /// Like `JsonObject::insert`.
+    pub fn insert(&mut self, key: &str, value: JsonValue) -> Result<(), Error> {
+        self.set(&pointer::join(&[key]), value)
+    }
+
+    /// Like `JsonObject::delete_key`, doing nothing if the key is missing.
+    pub fn delete_key(&mut self, key: &str) -> Result<(), Error> {
+        if self.object.get_value(key).is_none() {
+            return Ok(());
+        }
+        self.remove(&pointer::join(&[key]))
+    }
+
+    /// Sets the value at a JSON Pointer. The last token may be a new key, or
+    /// `-` or the length of an array to append to it.
+    pub fn set(&mut self, path: &str, value: JsonValue) -> Result<(), Error> {
+        let change = change::plan_set(self.object, path, value)?;
+        self.make(change)
+    }
+
+    /// Inserts into an array at the index `path` ends in, moving the
+    /// elements from there on back.
+    pub fn insert_element(&mut self, path: &str, value: JsonValue) -> Result<(), Error> {
+        let change = change::plan_insert_element(self.object, path, value)?;
+        self.make(change)
+    }
+
+    /// Removes the key or array element at `path`. A rollback puts it back
+    /// where it was.
+    pub fn remove(&mut self, path: &str) -> Result<(), Error> {
+        let change = change::plan_remove(self.object, path)?;
+        self.make(change)
+    }
+
+    /// Keeps the edits and returns them.
+    pub fn commit(mut self) -> ChangeSet {
+        self.done = true;
+        ChangeSet {
+            changes: std::mem::take(&mut self.changes),
+        }
+    }
+
+    /// Undoes all edits made through this transaction, checking each one
+    /// like `ChangeSet::revert` does.
+    pub fn rollback(mut self) -> Result<(), Error> {
+        self.undo_all()
+    }
+
+    fn undo_all(&mut self) -> Result<(), Error> {
+        self.done = true;
+        let changes = std::mem::take(&mut self.changes);
+        undo(self.object, &changes)
+    }
+}
+
+impl<'a> Drop for Transaction<'a> {
+    fn drop(&mut self) {
+        if !self.done {
+            // nothing else can change the object while the transaction
+            // borrows it, so the checks pass
+            let _ = self.undo_all();
+        }
+    }
+}
+
+/// The last committed change sets, for undo and redo. Holds at most
+/// `limit` of them, dropping the oldest first.
+#[derive(Debug)]
+pub struct History {
+    undo: VecDeque<ChangeSet>,
+    redo: Vec<ChangeSet>,
+    limit: usize,
+}
+
+impl History {
+    pub fn new(limit: usize) -> History {
+        History {
+            undo: VecDeque::new(),
+            redo: vec![],
+            limit,
+        }
+    }
+
+    /// Adds a committed change set, which makes everything undone so far
+    /// impossible to redo. Empty change sets are ignored.
+    pub fn record(&mut self, changes: ChangeSet) {
+        if changes.is_empty() || self.limit == 0 {
+            return;
+        }
+        self.redo.clear();
+        if self.undo.len() == self.limit {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(changes);
+    }
+
+    /// Reverts the last change set, returning it, or `None` if there is
+    /// nothing left to undo. On an error, `object` and the history are
+    /// left as they were.
+    pub fn undo(&mut self, object: &mut JsonObject) -> Result<Option<&ChangeSet>, Error> {
+        let changes = match self.undo.pop_back() {
+            Some(changes) => changes,
+            None => return Ok(None),
+        };
+        if let Err(err) = changes.revert(object) {
+            self.undo.push_back(changes);
+            return Err(err);
+        }
+        self.redo.push(changes);
+        Ok(self.redo.last())
+    }
+
+    /// Makes the last undone change set again, returning it, or `None` if
+    /// there is nothing to redo. Like `undo`, changes nothing on an error.
+    pub fn redo(&mut self, object: &mut JsonObject) -> Result<Option<&ChangeSet>, Error> {
+        let changes = match self.redo.pop() {
+            Some(changes) => changes,
+            None => return Ok(None),
+        };
+        if let Err(err) = changes.apply(object) {
+            self.redo.push(changes);
+            return Err(err);
+        }
+        self.undo.push_back(changes);
+        Ok(self.undo.back())
+    }
+
+    /// The change sets that can be undone, oldest first.
+    pub fn undo_stack(&self) -> impl Iterator<Item = &ChangeSet> {
+        self.undo.iter()
+    }
+
+    /// The change sets that can be redone, the next one last.
+    pub fn redo_stack(&self) -> impl Iterator<Item = &ChangeSet> {
+        self.redo.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{config, int};
+
+    #[test]
+    fn rollback_restores_everything() {
+        let mut object = config();
+        let original = object.to_string_compact().unwrap();
+
+        let mut transaction = Transaction::new(&mut object);
+        transaction.insert("port", int(8080)).unwrap();
+        transaction.delete_key("name").unwrap();
+        transaction
+            .set("/hosts/-", JsonValue::String("c".to_string()))
+            .unwrap();
+        transaction
+            .insert_element("/hosts/0", JsonValue::Null)
+            .unwrap();
+        transaction.remove("/hosts/2").unwrap();
+        transaction
+            .set("/tls/enabled", JsonValue::Bool(true))
+            .unwrap();
+        assert_eq!(
+            transaction.object().to_string_compact().unwrap(),
+            "{\"port\":8080,\"hosts\":[null,\"a\",\"c\"],\"tls\":{\"enabled\":true}}"
+        );
+        transaction.rollback().unwrap();
+
+        assert_eq!(object.to_string_compact().unwrap(), original);
+    }
+
+    #[test]
+    fn drop_without_commit_rolls_back() {
+        let mut object = config();
+        {
+            let mut transaction = Transaction::new(&mut object);
+            transaction.insert("port", int(1)).unwrap();
+            assert!(transaction.set("/missing/key", int(2)).is_err());
+            assert!(matches!(
+                transaction.set("port", int(2)),
+                Err(Error::InvalidPointer(path)) if path == "port"
+            ));
+        }
+        assert_eq!(object, config());
+    }
+
+    #[test]
+    fn undo_and_redo_committed_changes() {
+        let mut object = config();
+        let mut history = History::new(2);
+
+        for port in 1..=3 {
+            let mut transaction = Transaction::new(&mut object);
+            transaction.insert("port", int(port)).unwrap();
+            history.record(transaction.commit());
+        }
+        assert_eq!(history.undo_stack().count(), 2);
+
+        let undone = history.undo(&mut object).unwrap().unwrap();
+        assert_eq!(
+            undone.changes(),
+            &[Change::Replace {
+                path: "/port".to_string(),
+                old: int(2),
+                new: int(3),
+            }]
+        );
+        history.undo(&mut object).unwrap();
+        assert_eq!(object.get_value("port"), Some(&int(1)));
+        assert!(history.undo(&mut object).unwrap().is_none());
+
+        history.redo(&mut object).unwrap();
+        assert_eq!(object.get_value("port"), Some(&int(2)));
+
+        let mut transaction = Transaction::new(&mut object);
+        transaction.delete_key("tls").unwrap();
+        history.record(transaction.commit());
+        assert!(history.redo(&mut object).unwrap().is_none());
+        assert_eq!(object.get_keys(), vec!["name", "port", "hosts"]);
+        history.undo(&mut object).unwrap();
+        assert_eq!(object.get_keys(), vec!["name", "port", "hosts", "tls"]);
+    }
+
+    #[test]
+    fn failed_apply_changes_nothing() {
+        let mut object = config();
+        let mut transaction = Transaction::new(&mut object);
+        transaction.insert("port", int(1)).unwrap();
+        transaction.remove("/hosts/1").unwrap();
+        transaction.delete_key("tls").unwrap();
+        let changes = transaction.commit();
+
+        let mut without_tls = config();
+        without_tls.delete_key("tls");
+        let before = without_tls.clone();
+        assert!(matches!(
+            changes.apply(&mut without_tls),
+            Err(Error::NotFound(path)) if path == "/tls"
+        ));
+        assert_eq!(without_tls, before);
+
+        let mut changed = config();
+        changes.apply(&mut changed).unwrap();
+        changed.insert("hosts".to_string(), JsonValue::Null);
+        let before = changed.clone();
+        let mut history = History::new(4);
+        history.record(changes);
+        // the key is put back before the array fails to take its element
+        assert!(matches!(
+            history.undo(&mut changed),
+            Err(Error::NotAContainer(_))
+        ));
+        assert_eq!(changed, before);
+        assert_eq!(history.undo_stack().count(), 1);
+    }
+
+    fn parse(json: &str) -> JsonObject {
+        crate::parse(json.to_string())
+    }
+
+    #[test]
+    fn apply_checks_recorded_values() {
+        let mut object = parse("{\"a\": 0, \"b\": 1}");
+        let mut transaction = Transaction::new(&mut object);
+        transaction.set("/a", int(1)).unwrap();
+        transaction.remove("/b").unwrap();
+        let changes = transaction.commit();
+
+        // the replaced value differs, so nothing is made
+        let mut diverged = parse("{\"x\": 0, \"a\": 5}");
+        assert!(matches!(
+            changes.apply(&mut diverged),
+            Err(Error::Conflict(path)) if path == "/a"
+        ));
+        assert_eq!(diverged, parse("{\"x\": 0, \"a\": 5}"));
+
+        // the removed value or its position differs, after /a was replaced
+        for json in &["{\"a\": 0, \"b\": 2}", "{\"b\": 1, \"a\": 0}"] {
+            let mut diverged = parse(json);
+            assert!(matches!(
+                changes.apply(&mut diverged),
+                Err(Error::Conflict(path)) if path == "/b"
+            ));
+            assert_eq!(diverged.get_keys(), parse(json).get_keys());
+            assert_eq!(diverged, parse(json));
+        }
+    }
+
+    #[test]
+    fn revert_checks_recorded_values() {
+        let mut object = config();
+        let mut transaction = Transaction::new(&mut object);
+        transaction.insert("port", int(1)).unwrap();
+        transaction.remove("/hosts/0").unwrap();
+        let changes = transaction.commit();
+
+        // the host is put back before the port turns out to differ
+        let mut diverged = object.clone();
+        diverged.insert("port".to_string(), int(2));
+        let before = diverged.clone();
+        assert!(matches!(
+            changes.revert(&mut diverged),
+            Err(Error::Conflict(path)) if path == "/port"
+        ));
+        assert_eq!(diverged, before);
+
+        changes.revert(&mut object).unwrap();
+        assert_eq!(object, config());
+    }
+
+    #[test]
+    fn insert_rejects_existing_keys() {
+        let mut object = config();
+        let change = Change::Insert {
+            path: "/port".to_string(),
+            index: 0,
+            value: int(1),
+        };
+        assert!(matches!(
+            change::apply(&mut object, &change),
+            Err(Error::KeyExists(_))
+        ));
+        assert_eq!(object, config());
+    }
+}
@@ -1,10 +1,20 @@
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::error;
 use std::fmt;
-use std::{
-    collections::{hash_map, HashMap},
-    vec,
-};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::{Deref, Index, IndexMut};
+use std::{slice, sync::Arc, vec};
 
-#[derive(Clone, Debug, PartialEq)]
+pub use crate::array::JsonArray;
+use crate::color::{self, Theme};
+use crate::error::Error;
+use crate::pointer;
+use crate::serializer::{self, PrettyConfig};
+use crate::summary::{self, SummaryConfig};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum JsonNum {
     Int(i64),
     Float(f64),
@@ -12,11 +22,14 @@ pub enum JsonNum {
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum JsonValue {
+    /// The decoded text of a string, without its quotes and with its
+    /// escape sequences resolved, so `"a\"b"` holds `a"b`. Keys of a
+    /// `JsonObject` are decoded the same way.
     String(String),
     Num(JsonNum),
     Bool(bool),
     Null,
-    Vec(Vec<JsonValue>), // ok so this works! self referencing
+    Vec(JsonArray), // ok so this works! self referencing
     Object(JsonObject),
 }
 
@@ -32,100 +45,669 @@ pub fn strip_quotes(string: String) -> String {
     }
 }
 
+impl JsonValue {
+    pub fn to_string_compact(&self) -> Result<String, Error> {
+        serializer::to_string_compact(self)
+    }
+
+    pub fn to_string_pretty(&self, config: &PrettyConfig) -> Result<String, Error> {
+        serializer::to_string_pretty(self, config)
+    }
+
+    pub fn to_string_colored(&self, config: &PrettyConfig, theme: &Theme) -> Result<String, Error> {
+        color::to_string_colored(self, config, theme)
+    }
+
+    pub fn to_string_summary(&self, config: &SummaryConfig) -> String {
+        summary::to_string_summary(self, config)
+    }
+
+    /// The name of this type of value, such as `"string"` or `"array"`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            JsonValue::String(_) => "string",
+            JsonValue::Num(_) => "number",
+            JsonValue::Bool(_) => "boolean",
+            JsonValue::Null => "null",
+            JsonValue::Vec(_) => "array",
+            JsonValue::Object(_) => "object",
+        }
+    }
+
+    /// Like `type_name`, but telling the kinds of numbers apart, so that a
+    /// mismatch on a number says why it didn't fit.
+    pub(crate) fn kind_name(&self) -> &'static str {
+        match self {
+            JsonValue::Num(JsonNum::Int(int)) if *int < 0 => "negative integer",
+            JsonValue::Num(JsonNum::Int(_)) => "integer",
+            JsonValue::Num(JsonNum::Float(_)) => "float",
+            other => other.type_name(),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// The number if it is an integer. Floats are not converted, even
+    /// when they have no fractional part.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            JsonValue::Num(JsonNum::Int(int)) => Some(*int),
+            _ => None,
+        }
+    }
+
+    /// The number if it is an integer that isn't negative.
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_i64().and_then(|int| u64::try_from(int).ok())
+    }
+
+    /// Any number, converting integers.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Num(JsonNum::Int(int)) => Some(*int as f64),
+            JsonValue::Num(JsonNum::Float(float)) => Some(*float),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(bool) => Some(*bool),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&JsonArray> {
+        match self {
+            JsonValue::Vec(vec) => Some(vec),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&JsonObject> {
+        match self {
+            JsonValue::Object(object) => Some(object),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, JsonValue::Null)
+    }
+
+    /// The value at a JSON Pointer such as `/files/0`, relative to this one.
+    /// Returns `None` for a pointer that doesn't start with a slash.
+    pub fn get_in(&self, pointer: &str) -> Option<&JsonValue> {
+        self.get_tokens(&pointer::split(pointer).ok()?)
+    }
+
+    /// Like `get_in`, for a pointer that is already split.
+    pub(crate) fn get_tokens(&self, tokens: &[String]) -> Option<&JsonValue> {
+        tokens.iter().try_fold(self, |value, token| match value {
+            JsonValue::Object(object) => object.get_value(token),
+            JsonValue::Vec(vec) => token.parse::<usize>().ok().and_then(|i| vec.get(i)),
+            _ => None,
+        })
+    }
+}
+
+/// Writes the compact form. A NaN or infinite float fails with
+/// `fmt::Error`; use `to_string_compact` to see why.
 impl fmt::Display for JsonValue {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Self::String(str) => {
-                // I think it's clearer that it is a string if
-                // we print it with quotes
-                let str_with_quotes = format!("\"{}\"", str);
-                return formatter.write_str(&str_with_quotes);
+        formatter.write_str(&self.to_string_compact().map_err(|_| fmt::Error)?)
+    }
+}
+
+macro_rules! from_num {
+    ($($from:ty => $variant:ident),*) => {
+        $(impl From<$from> for JsonValue {
+            fn from(num: $from) -> JsonValue {
+                JsonValue::Num(JsonNum::$variant(num.into()))
             }
-            Self::Num(num) => match num {
-                &JsonNum::Float(float) => {
-                    let output = float.to_string();
-                    return formatter.write_str(&output);
-                }
-                &JsonNum::Int(int) => {
-                    let output = int.to_string();
-                    return formatter.write_str(&output);
-                }
-            },
-            Self::Vec(vec) => {
-                let mut vec_builder: Vec<String> = vec![];
+        })*
+    };
+}
+
+from_num!(i32 => Int, u32 => Int, i64 => Int, f32 => Float, f64 => Float);
+
+impl From<JsonNum> for JsonValue {
+    fn from(num: JsonNum) -> JsonValue {
+        JsonValue::Num(num)
+    }
+}
+
+impl From<bool> for JsonValue {
+    fn from(bool: bool) -> JsonValue {
+        JsonValue::Bool(bool)
+    }
+}
+
+impl From<String> for JsonValue {
+    fn from(string: String) -> JsonValue {
+        JsonValue::String(string)
+    }
+}
+
+impl From<&str> for JsonValue {
+    fn from(string: &str) -> JsonValue {
+        JsonValue::String(string.to_string())
+    }
+}
+
+impl From<JsonArray> for JsonValue {
+    fn from(array: JsonArray) -> JsonValue {
+        JsonValue::Vec(array)
+    }
+}
+
+impl<T: Into<JsonValue>> From<Vec<T>> for JsonValue {
+    fn from(vec: Vec<T>) -> JsonValue {
+        JsonValue::Vec(vec.into_iter().collect())
+    }
+}
+
+impl From<JsonObject> for JsonValue {
+    fn from(object: JsonObject) -> JsonValue {
+        JsonValue::Object(object)
+    }
+}
+
+/// What indexing returns for a missing key or element, or a value of the
+/// wrong type.
+static NULL: JsonValue = JsonValue::Null;
+
+/// `value["key"]` is the value of `key` if this is an object that has it,
+/// and `Null` otherwise.
+impl Index<&str> for JsonValue {
+    type Output = JsonValue;
+
+    fn index(&self, key: &str) -> &JsonValue {
+        match self {
+            JsonValue::Object(object) => &object[key],
+            _ => &NULL,
+        }
+    }
+}
+
+/// `value[0]` is the element if this is an array that long, and `Null`
+/// otherwise.
+impl Index<usize> for JsonValue {
+    type Output = JsonValue;
+
+    fn index(&self, index: usize) -> &JsonValue {
+        match self {
+            JsonValue::Vec(vec) => vec.get(index).unwrap_or(&NULL),
+            _ => &NULL,
+        }
+    }
+}
+
+/// Assigning to `value["key"]` turns a `Null` value into an empty object
+/// and adds the key as `Null` if it is missing, so nested objects can be
+/// built up as in `value["a"]["b"] = ...`. Panics for any other type.
+impl IndexMut<&str> for JsonValue {
+    fn index_mut(&mut self, key: &str) -> &mut JsonValue {
+        if let JsonValue::Null = self {
+            *self = JsonValue::Object(JsonObject::new());
+        }
+        match self {
+            JsonValue::Object(object) => &mut object[key],
+            unexpected => panic!("Cannot index into {:?} with key {:?}", unexpected, key),
+        }
+    }
+}
 
-                for value in vec {
-                    vec_builder.push(value.to_string());
+/// Panics unless this is an array with an element at `index`.
+impl IndexMut<usize> for JsonValue {
+    fn index_mut(&mut self, index: usize) -> &mut JsonValue {
+        match self {
+            JsonValue::Vec(vec) => {
+                let len = vec.len();
+                match vec.get_mut(index) {
+                    Some(value) => value,
+                    None => panic!(
+                        "Index {} is out of bounds for array of length {}",
+                        index, len
+                    ),
                 }
+            }
+            unexpected => panic!("Cannot index into {:?} with index {}", unexpected, index),
+        }
+    }
+}
+
+/// A value of a different type than asked for was found at a key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeMismatch {
+    pub key: String,
+    /// What was asked for, such as `"string"` or `"unsigned integer"`.
+    pub expected: &'static str,
+    /// The `JsonValue::type_name` of what was found, except that numbers
+    /// are a `"negative integer"`, an `"integer"` or a `"float"`.
+    pub actual: &'static str,
+}
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "Expected {} for key {:?}, found {}",
+            self.expected, self.key, self.actual
+        )
+    }
+}
+
+impl error::Error for TypeMismatch {}
+
+/// Objects with at most this many members are searched linearly, which
+/// beats hashing for the small objects most documents are made of. See
+/// `benches/object.rs` for where the two cross over.
+pub(crate) const SMALL_OBJECT_LEN: usize = 24;
+
+/// The positions of the members of a large `JsonObject`, looked up by the
+/// hash of their key. The keys themselves are only kept in the object's
+/// `entries`.
+#[derive(Clone, Debug)]
+struct KeyIndex {
+    hasher: RandomState,
+    /// Open addressing with linear probing. Holds a position in `entries`,
+    /// or `EMPTY`, and is kept at most half full.
+    slots: Vec<usize>,
+}
+
+const EMPTY: usize = usize::MAX;
+
+impl KeyIndex {
+    fn new(entries: &[(Key, JsonValue)]) -> KeyIndex {
+        let mut index = KeyIndex {
+            hasher: RandomState::new(),
+            slots: vec![EMPTY; (entries.len() * 2).next_power_of_two()],
+        };
+        for position in 0..entries.len() {
+            index.add(entries, position);
+        }
+        index
+    }
+
+    /// The slot `key` is looked for from.
+    fn home(&self, key: &str) -> usize {
+        let mut hasher = self.hasher.build_hasher();
+        key.hash(&mut hasher);
+        hasher.finish() as usize & (self.slots.len() - 1)
+    }
 
-                let joined_vec = vec_builder.join(", ");
-                let final_vec = format!("[{}]", joined_vec);
-                return formatter.write_str(&final_vec);
+    fn find(&self, entries: &[(Key, JsonValue)], key: &str) -> Option<usize> {
+        let mut slot = self.home(key);
+        loop {
+            match self.slots[slot] {
+                EMPTY => return None,
+                position if &*entries[position].0 == key => return Some(position),
+                _ => slot = (slot + 1) & (self.slots.len() - 1),
             }
-            Self::Bool(bool) => {
-                return formatter.write_str(&bool.to_string());
+        }
+    }
+
+    /// Adds the member at `position`, whose key must not be in the index
+    /// yet, growing the index if it gets more than half full.
+    fn add(&mut self, entries: &[(Key, JsonValue)], position: usize) {
+        if entries.len() * 2 > self.slots.len() {
+            *self = KeyIndex::new(entries);
+            return;
+        }
+        let mut slot = self.home(&entries[position].0);
+        while self.slots[slot] != EMPTY {
+            slot = (slot + 1) & (self.slots.len() - 1);
+        }
+        self.slots[slot] = position;
+    }
+
+    /// Adds the member inserted at `position`, moving the members after it
+    /// one position back.
+    fn insert(&mut self, entries: &[(Key, JsonValue)], position: usize) {
+        for slot in &mut self.slots {
+            if *slot != EMPTY && *slot >= position {
+                *slot += 1;
             }
-            Self::Object(obj) => {
-                let mut obj_builder: Vec<String> = vec![];
+        }
+        self.add(entries, position);
+    }
+}
 
-                for (key, val) in obj.to_iter() {
-                    obj_builder.push(format!("\"{}\": {}", key, val));
-                }
+/// The key of a member of a `JsonObject`. A parsed key keeps the `String`
+/// the lexer decoded, and a key from a `KeyInterner` or given as an
+/// `Arc<str>` is shared, so neither is copied on the way in.
+#[derive(Clone, Debug)]
+pub struct Key(KeyRepr);
+
+#[derive(Clone, Debug)]
+enum KeyRepr {
+    Owned(String),
+    Shared(Arc<str>),
+}
 
-                let joined_obj = obj_builder.join(",");
-                let with_brackets = format!("{{{}}}", joined_obj);
+impl Deref for Key {
+    type Target = str;
 
-                return formatter.write_str(&with_brackets);
-            }
-            Self::Null => return formatter.write_str("null"),
+    fn deref(&self) -> &str {
+        match &self.0 {
+            KeyRepr::Owned(key) => key,
+            KeyRepr::Shared(key) => key,
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+impl From<String> for Key {
+    fn from(key: String) -> Key {
+        Key(KeyRepr::Owned(key))
+    }
+}
+
+impl From<&str> for Key {
+    fn from(key: &str) -> Key {
+        Key(KeyRepr::Owned(key.to_string()))
+    }
+}
+
+impl From<Arc<str>> for Key {
+    fn from(key: Arc<str>) -> Key {
+        Key(KeyRepr::Shared(key))
+    }
+}
+
+impl From<Key> for String {
+    fn from(key: Key) -> String {
+        match key.0 {
+            KeyRepr::Owned(key) => key,
+            KeyRepr::Shared(key) => key.to_string(),
+        }
+    }
+}
+
+/// An object that keeps its members in the order they were parsed or
+/// inserted, which is the order they are iterated and printed in.
+#[derive(Clone, Debug)]
 pub struct JsonObject {
-    json: HashMap<String, JsonValue>,
+    entries: Vec<(Key, JsonValue)>,
+    /// Only built once there are more than `SMALL_OBJECT_LEN` members.
+    index: Option<KeyIndex>,
+}
+
+/// Objects are equal if they have the same members, in any order.
+impl PartialEq for JsonObject {
+    fn eq(&self, other: &JsonObject) -> bool {
+        self.entries.len() == other.entries.len()
+            && self
+                .entries
+                .iter()
+                .all(|(key, value)| other.get_value(key) == Some(value))
+    }
+}
+
+/// Iterates over the members of a `JsonObject` in order, with keys as
+/// `&str` like `JsonObject::get_keys`.
+pub struct Iter<'a> {
+    entries: slice::Iter<'a, (Key, JsonValue)>,
 }
 
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, &'a JsonValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|(key, value)| (&**key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+/// Iterates over the members of a `JsonObject` in order, with mutable
+/// values.
+pub struct IterMut<'a> {
+    entries: slice::IterMut<'a, (Key, JsonValue)>,
+}
+
+impl<'a> Iterator for IterMut<'a> {
+    type Item = (&'a str, &'a mut JsonValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|(key, value)| (&**key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for IterMut<'a> {}
+
+/// Moves the members out of a `JsonObject` in order.
+pub struct IntoIter {
+    entries: vec::IntoIter<(Key, JsonValue)>,
+}
+
+impl Iterator for IntoIter {
+    type Item = (String, JsonValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries
+            .next()
+            .map(|(key, value)| (key.into(), value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl ExactSizeIterator for IntoIter {}
+
 impl JsonObject {
     pub fn new() -> Self {
         return JsonObject {
-            json: HashMap::new(),
+            entries: vec![],
+            index: None,
         };
     }
 
     pub fn print(&mut self) {
-        let mut print_iter = self.json.clone().into_iter();
-
-        println!("{{");
-        while let Some((key, value)) = print_iter.next() {
-            println!("  \"{}\": {}", key, value);
+        match self.to_string_pretty(&PrettyConfig::default()) {
+            Ok(string) => println!("{}", string),
+            Err(err) => eprintln!("{}", err),
         }
-        println!("}}");
+    }
+
+    pub fn to_string_compact(&self) -> Result<String, Error> {
+        serializer::object_to_string_compact(self)
+    }
+
+    pub fn to_string_pretty(&self, config: &PrettyConfig) -> Result<String, Error> {
+        serializer::object_to_string_pretty(self, config)
     }
 
     pub fn empty(&mut self) {
-        self.json.clear();
+        self.entries.clear();
+        self.index = None;
     }
 
+    /// The position of `key` among the members.
+    pub(crate) fn position(&self, key: &str) -> Option<usize> {
+        match &self.index {
+            Some(index) => index.find(&self.entries, key),
+            None => self.entries.iter().position(|(entry, _)| &**entry == key),
+        }
+    }
+
+    /// Builds the index if there are too many members to search, or drops
+    /// it if there aren't.
+    fn reindex(&mut self) {
+        self.index = if self.entries.len() > SMALL_OBJECT_LEN {
+            Some(KeyIndex::new(&self.entries))
+        } else {
+            None
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    /// Removes `key`, keeping the order of the members after it.
     pub fn delete_key(&mut self, key: &str) {
-        self.json.remove(key);
+        self.remove(key);
+    }
+
+    /// Removes `key` like `delete_key`, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<JsonValue> {
+        let position = self.position(key)?;
+        let (_, value) = self.entries.remove(position);
+        // the members after it all move, so every position changes anyway
+        self.reindex();
+        Some(value)
+    }
+
+    /// Keeps only the members `keep` returns true for, in order.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&str, &mut JsonValue) -> bool,
+    {
+        self.entries.retain_mut(|(key, value)| keep(key, value));
+        self.reindex();
+    }
+
+    /// Gives the member `old` the key `new`, keeping its position. A member
+    /// that already has the key `new` is removed. Returns whether there was
+    /// a member `old`.
+    pub fn rename_key<K: Into<Key>>(&mut self, old: &str, new: K) -> bool {
+        let mut position = match self.position(old) {
+            Some(position) => position,
+            None => return false,
+        };
+        let new = new.into();
+        if &*new != old {
+            if let Some(existing) = self.position(&new) {
+                self.entries.remove(existing);
+                if existing < position {
+                    position -= 1;
+                }
+            }
+        }
+        self.entries[position].0 = new;
+        self.reindex();
+        true
+    }
+
+    /// The member `key`, to look at or change, or the place to add it.
+    pub fn entry<K: Into<Key>>(&mut self, key: K) -> Entry<'_> {
+        let key = key.into();
+        match self.position(&key) {
+            Some(position) => Entry::Occupied(OccupiedEntry {
+                object: self,
+                position,
+            }),
+            None => Entry::Vacant(VacantEntry { object: self, key }),
+        }
     }
 
+    /// Adds a member at the end, or replaces the value of an existing key
+    /// where it is.
     pub fn insert(&mut self, key: String, value: JsonValue) {
-        self.json.insert(key, value);
+        self.insert_key(key.into(), value);
+    }
+
+    /// Like `insert`, but shares `key`, such as one from a `KeyInterner`,
+    /// instead of taking a `String`.
+    pub fn insert_shared(&mut self, key: Arc<str>, value: JsonValue) {
+        self.insert_key(key.into(), value);
+    }
+
+    fn insert_key(&mut self, key: Key, value: JsonValue) {
+        if let Some(position) = self.position(&key) {
+            self.entries[position].1 = value;
+            return;
+        }
+
+        self.entries.push((key, value));
+        match &mut self.index {
+            Some(index) => index.add(&self.entries, self.entries.len() - 1),
+            None => self.reindex(),
+        }
+    }
+
+    /// Adds a new key at `position` among the members, moving the ones from
+    /// there on back. Returns false, changing nothing, if the key is
+    /// already in the object. Panics if `position` is past the end.
+    pub(crate) fn insert_at<K: Into<Key>>(
+        &mut self,
+        position: usize,
+        key: K,
+        value: JsonValue,
+    ) -> bool {
+        let key = key.into();
+        if self.position(&key).is_some() {
+            return false;
+        }
+        self.entries.insert(position, (key, value));
+        match &mut self.index {
+            Some(index) => index.insert(&self.entries, position),
+            None => self.reindex(),
+        }
+        true
     }
 
     pub fn get_value(&self, key: &str) -> Option<&JsonValue> {
-        return self.json.get(key);
+        let position = self.position(key)?;
+        Some(&self.entries[position].1)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut JsonValue> {
+        let position = self.position(key)?;
+        Some(&mut self.entries[position].1)
+    }
+
+    #[deprecated(note = "renamed to `get_mut`")]
+    pub fn get_value_mut(&mut self, key: &str) -> Option<&mut JsonValue> {
+        self.get_mut(key)
+    }
+
+    /// The members in order. This used to return a `hash_map::Iter` with
+    /// `&String` keys, but the members are no longer kept in a `HashMap`,
+    /// and a shared key has no `String` to borrow, so the keys are `&str`.
+    pub fn to_iter(&self) -> Iter<'_> {
+        Iter {
+            entries: self.entries.iter(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        IterMut {
+            entries: self.entries.iter_mut(),
+        }
     }
 
-    pub fn to_iter(&self) -> hash_map::Iter<String, JsonValue> {
-        return self.json.iter();
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut JsonValue> {
+        self.entries.iter_mut().map(|(_, value)| value)
     }
 
+    #[deprecated(note = "use `get_str`, which returns an error instead of panicking")]
     pub fn get_string_value(&self, key: &str) -> Option<&String> {
-        let val = self.json.get(key);
+        let val = self.get_value(key);
         if val.is_none() {
             return None;
         }
@@ -144,12 +726,219 @@ impl JsonObject {
         }
     }
 
-    pub fn get_keys(&self) -> Vec<&String> {
-        let raw_keys = self.json.keys().into_iter();
-        let mut keys: Vec<&String> = vec![];
+    /// The keys in order. These used to be `&String`, but a shared key has
+    /// no `String` to borrow. Callers that copy them, as with
+    /// `get_keys().iter().map(|key| key.to_string())`, work with either.
+    pub fn get_keys(&self) -> Vec<&str> {
+        self.entries.iter().map(|(key, _)| &**key).collect()
+    }
+
+    /// Converts the value of `key` with one of the `JsonValue::as_*`
+    /// methods. A missing key is `Ok(None)`.
+    fn get_as<'a, T>(
+        &'a self,
+        key: &str,
+        expected: &'static str,
+        convert: fn(&'a JsonValue) -> Option<T>,
+    ) -> Result<Option<T>, TypeMismatch> {
+        let value = match self.get_value(key) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        match convert(value) {
+            Some(converted) => Ok(Some(converted)),
+            None => Err(TypeMismatch {
+                key: key.to_string(),
+                expected,
+                actual: value.kind_name(),
+            }),
+        }
+    }
+
+    pub fn get_str(&self, key: &str) -> Result<Option<&str>, TypeMismatch> {
+        self.get_as(key, "string", JsonValue::as_str)
+    }
 
-        raw_keys.for_each(|x| keys.push(x));
-        return keys;
+    pub fn get_i64(&self, key: &str) -> Result<Option<i64>, TypeMismatch> {
+        self.get_as(key, "integer", JsonValue::as_i64)
+    }
+
+    pub fn get_u64(&self, key: &str) -> Result<Option<u64>, TypeMismatch> {
+        self.get_as(key, "unsigned integer", JsonValue::as_u64)
+    }
+
+    pub fn get_f64(&self, key: &str) -> Result<Option<f64>, TypeMismatch> {
+        self.get_as(key, "number", JsonValue::as_f64)
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, TypeMismatch> {
+        self.get_as(key, "boolean", JsonValue::as_bool)
+    }
+
+    pub fn get_array(&self, key: &str) -> Result<Option<&JsonArray>, TypeMismatch> {
+        self.get_as(key, "array", JsonValue::as_array)
+    }
+
+    pub fn get_object(&self, key: &str) -> Result<Option<&JsonObject>, TypeMismatch> {
+        self.get_as(key, "object", JsonValue::as_object)
+    }
+}
+
+/// `object["key"]` is the value of `key`, or `Null` if it is missing.
+impl Index<&str> for JsonObject {
+    type Output = JsonValue;
+
+    fn index(&self, key: &str) -> &JsonValue {
+        self.get_value(key).unwrap_or(&NULL)
+    }
+}
+
+/// Assigning to `object["key"]` adds the key as `Null` if it is missing.
+impl IndexMut<&str> for JsonObject {
+    fn index_mut(&mut self, key: &str) -> &mut JsonValue {
+        // looked up first, as the key only has to be copied if it is new
+        let position = match self.position(key) {
+            Some(position) => position,
+            None => {
+                self.insert_key(key.into(), JsonValue::Null);
+                self.entries.len() - 1
+            }
+        };
+        &mut self.entries[position].1
+    }
+}
+
+impl IntoIterator for JsonObject {
+    type Item = (String, JsonValue);
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter {
+            entries: self.entries.into_iter(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a JsonObject {
+    type Item = (&'a str, &'a JsonValue);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.to_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut JsonObject {
+    type Item = (&'a str, &'a mut JsonValue);
+    type IntoIter = IterMut<'a>;
+
+    fn into_iter(self) -> IterMut<'a> {
+        self.iter_mut()
+    }
+}
+
+/// Inserts the members in order, so a later duplicate key replaces the
+/// value of an earlier one where it is.
+impl<K: Into<Key>> Extend<(K, JsonValue)> for JsonObject {
+    fn extend<I: IntoIterator<Item = (K, JsonValue)>>(&mut self, members: I) {
+        for (key, value) in members {
+            self.insert_key(key.into(), value);
+        }
+    }
+}
+
+impl<K: Into<Key>> FromIterator<(K, JsonValue)> for JsonObject {
+    fn from_iter<I: IntoIterator<Item = (K, JsonValue)>>(members: I) -> JsonObject {
+        let mut object = JsonObject::new();
+        object.extend(members);
+        object
+    }
+}
+
+/// A member of a `JsonObject`, from `JsonObject::entry`.
+pub enum Entry<'a> {
+    Occupied(OccupiedEntry<'a>),
+    Vacant(VacantEntry<'a>),
+}
+
+pub struct OccupiedEntry<'a> {
+    object: &'a mut JsonObject,
+    position: usize,
+}
+
+pub struct VacantEntry<'a> {
+    object: &'a mut JsonObject,
+    key: Key,
+}
+
+impl<'a> Entry<'a> {
+    pub fn key(&self) -> &str {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => &entry.key,
+        }
+    }
+
+    /// The value, after adding `default` if the key is missing.
+    pub fn or_insert(self, default: JsonValue) -> &'a mut JsonValue {
+        self.or_insert_with(|| default)
+    }
+
+    /// The value, after adding the result of `default` if the key is
+    /// missing.
+    pub fn or_insert_with<F: FnOnce() -> JsonValue>(self, default: F) -> &'a mut JsonValue {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Calls `modify` with the value if the key is there.
+    pub fn and_modify<F: FnOnce(&mut JsonValue)>(mut self, modify: F) -> Entry<'a> {
+        if let Entry::Occupied(entry) = &mut self {
+            modify(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a> OccupiedEntry<'a> {
+    pub fn key(&self) -> &str {
+        &self.object.entries[self.position].0
+    }
+
+    pub fn get(&self) -> &JsonValue {
+        &self.object.entries[self.position].1
+    }
+
+    pub fn get_mut(&mut self) -> &mut JsonValue {
+        &mut self.object.entries[self.position].1
+    }
+
+    pub fn into_mut(self) -> &'a mut JsonValue {
+        &mut self.object.entries[self.position].1
+    }
+
+    /// Replaces the value, returning the old one.
+    pub fn insert(&mut self, value: JsonValue) -> JsonValue {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> JsonValue {
+        let key = self.object.entries[self.position].0.clone();
+        self.object.remove(&key).unwrap()
+    }
+}
+
+impl<'a> VacantEntry<'a> {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Adds the member at the end.
+    pub fn insert(self, value: JsonValue) -> &'a mut JsonValue {
+        self.object.insert_key(self.key, value);
+        &mut self.object.entries.last_mut().unwrap().1
     }
 }
 
@@ -168,6 +957,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_get_string_value() {
         let mut obj = JsonObject::new();
         obj.insert("str".to_owned(), JsonValue::String("String".to_owned()));
@@ -180,6 +970,7 @@ mod tests {
     #[should_panic(
         expected = "The value for that key is not of type String, instead it is: Bool(false)"
     )]
+    #[allow(deprecated)]
     fn test_get_string_value_faulty() {
         let mut obj = JsonObject::new();
         obj.insert("str".to_owned(), JsonValue::Bool(false));
@@ -195,5 +986,222 @@ mod tests {
 
         let keys = obj.get_keys();
         assert_eq!(keys, vec![&"true".to_owned(), &"false".to_owned()]);
+        let copied: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+        assert_eq!(copied, vec!["true", "false"]);
+    }
+
+    #[test]
+    fn keeps_insertion_order() {
+        let mut obj = JsonObject::new();
+        for key in ["c", "a", "d", "b"].iter() {
+            obj.insert(key.to_string(), JsonValue::Null);
+        }
+        obj.insert("a".to_owned(), JsonValue::Bool(true));
+        obj.delete_key("d");
+
+        let keys: Vec<&str> = obj.to_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec!["c", "a", "b"]);
+        assert_eq!(obj.get_value("b"), Some(&JsonValue::Null));
+        assert_eq!(obj.to_string_compact().unwrap(), "{\"c\":null,\"a\":true,\"b\":null}");
+    }
+
+    #[test]
+    fn switches_to_index_and_back() {
+        let mut obj = JsonObject::new();
+        for i in 0..60 {
+            obj.insert(i.to_string(), JsonValue::Num(JsonNum::Int(i)));
+        }
+        assert!(obj.index.is_some());
+        assert_eq!(obj.get_value("13"), Some(&JsonValue::Num(JsonNum::Int(13))));
+
+        for i in (0..60).step_by(2) {
+            obj.delete_key(&i.to_string());
+        }
+        assert!(obj.index.is_some());
+        assert_eq!(obj.get_value("13"), Some(&JsonValue::Num(JsonNum::Int(13))));
+        assert_eq!(obj.get_value("12"), None);
+
+        for i in 0..20 {
+            obj.delete_key(&(i * 2 + 1).to_string());
+        }
+        assert!(obj.index.is_none());
+        assert_eq!(obj.get_keys()[..3], ["41", "43", "45"]);
+        assert_eq!(obj.get_value("57"), Some(&JsonValue::Num(JsonNum::Int(57))));
+    }
+
+    #[test]
+    fn insert_at_keeps_index_positions() {
+        let mut obj = JsonObject::new();
+        for i in 0..40 {
+            obj.insert(i.to_string(), JsonValue::Num(JsonNum::Int(i)));
+        }
+        assert!(obj.insert_at(5, "new", JsonValue::Null));
+        assert!(!obj.insert_at(0, "7", JsonValue::Null));
+
+        assert_eq!(obj.len(), 41);
+        assert_eq!(obj.get_keys()[4..7], ["4", "new", "5"]);
+        assert_eq!(obj.get_value("new"), Some(&JsonValue::Null));
+        for i in 0..40 {
+            assert_eq!(obj.get_value(&i.to_string()), Some(&JsonValue::Num(JsonNum::Int(i))));
+        }
+    }
+
+    #[test]
+    fn equality_ignores_order() {
+        let mut first = JsonObject::new();
+        first.insert("a".to_owned(), JsonValue::Null);
+        first.insert("b".to_owned(), JsonValue::Null);
+        let mut second = JsonObject::new();
+        second.insert("b".to_owned(), JsonValue::Null);
+        second.insert("a".to_owned(), JsonValue::Null);
+
+        assert_eq!(first, second);
+        second.insert("a".to_owned(), JsonValue::Bool(false));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn index_reads_null_when_missing() {
+        let doc = JsonValue::Object(crate::parse(
+            "{\"dependencies\": {\"eslint\": \"^8\"}, \"files\": [\"lib\"]}".to_string(),
+        ));
+
+        assert_eq!(
+            doc["dependencies"]["eslint"],
+            JsonValue::String("^8".to_owned())
+        );
+        assert_eq!(doc["files"][0], JsonValue::String("lib".to_owned()));
+        assert_eq!(doc["files"][1], JsonValue::Null);
+        assert_eq!(doc["files"]["name"], JsonValue::Null);
+        assert_eq!(doc["missing"]["deeper"][3], JsonValue::Null);
+    }
+
+    #[test]
+    fn index_mut_creates_objects() {
+        let mut obj = JsonObject::new();
+        obj["scripts"]["test"] = JsonValue::String("jest".to_owned());
+        obj["files"] = JsonValue::from(vec![JsonValue::Null]);
+        obj["files"][0] = JsonValue::Bool(true);
+
+        assert_eq!(
+            obj.to_string_compact().unwrap(),
+            "{\"scripts\":{\"test\":\"jest\"},\"files\":[true]}"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_mut_panics_on_type_mismatch() {
+        let mut value = JsonValue::Bool(true);
+        value["key"] = JsonValue::Null;
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let value = JsonValue::Object(crate::parse(
+            "{\"s\": \"x\", \"i\": -3, \"f\": 1.5, \"b\": true, \"n\": null, \"a\": []}"
+                .to_string(),
+        ));
+
+        assert_eq!(value["s"].as_str(), Some("x"));
+        assert_eq!(value["i"].as_i64(), Some(-3));
+        assert_eq!(value["i"].as_u64(), None);
+        assert_eq!(value["i"].as_f64(), Some(-3.0));
+        assert_eq!(value["f"].as_i64(), None);
+        assert_eq!(value["f"].as_f64(), Some(1.5));
+        assert_eq!(value["b"].as_bool(), Some(true));
+        assert!(value["n"].is_null());
+        assert_eq!(value["a"].as_array(), Some(&JsonArray::new()));
+        assert_eq!(value["s"].as_array(), None);
+        assert!(value.as_object().is_some());
+    }
+
+    #[test]
+    fn get_reports_type_mismatch() {
+        let obj = crate::parse("{\"name\": \"rson\", \"size\": -1}".to_string());
+
+        assert_eq!(obj.get_str("name"), Ok(Some("rson")));
+        assert_eq!(obj.get_str("missing"), Ok(None));
+        assert_eq!(obj.get_i64("size"), Ok(Some(-1)));
+        let err = obj.get_u64("size").unwrap_err();
+        assert_eq!(
+            err,
+            TypeMismatch {
+                key: "size".to_string(),
+                expected: "unsigned integer",
+                actual: "negative integer",
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Expected unsigned integer for key \"size\", found negative integer"
+        );
+        assert_eq!(obj.get_str("size").unwrap_err().actual, "negative integer");
+        assert_eq!(obj.get_object("name").unwrap_err().actual, "string");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn get_value_mut_still_works() {
+        let mut obj = crate::parse("{\"count\": 1}".to_string());
+        *obj.get_value_mut("count").unwrap() = JsonValue::Null;
+        assert_eq!(obj.get_value("count"), Some(&JsonValue::Null));
+        assert!(obj.get_value_mut("missing").is_none());
+    }
+
+    #[test]
+    fn entry_api() {
+        let mut obj = crate::parse("{\"count\": 1}".to_string());
+
+        obj.entry("count")
+            .and_modify(|count| *count = JsonValue::Num(JsonNum::Int(2)))
+            .or_insert(JsonValue::Null);
+        obj.entry("tags")
+            .and_modify(|_| panic!("tags is missing"))
+            .or_insert_with(|| JsonValue::Vec(JsonArray::new()));
+        if let JsonValue::Vec(tags) = obj.entry("tags".to_owned()).or_insert(JsonValue::Null) {
+            tags.push(JsonValue::Bool(true));
+        }
+        match obj.entry("count") {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), JsonValue::Num(JsonNum::Int(2))),
+            Entry::Vacant(_) => panic!("count is there"),
+        }
+
+        assert_eq!(obj.to_string_compact().unwrap(), "{\"tags\":[true]}");
+    }
+
+    #[test]
+    fn map_methods() {
+        let mut obj: JsonObject = (0..12)
+            .map(|i| (i.to_string(), JsonValue::Num(JsonNum::Int(i))))
+            .collect();
+        assert_eq!(obj.len(), 12);
+        assert!(obj.contains_key("11"));
+
+        assert_eq!(obj.remove("0"), Some(JsonValue::Num(JsonNum::Int(0))));
+        assert_eq!(obj.remove("0"), None);
+        obj.retain(|key, _| key.len() == 1);
+        assert_eq!(
+            obj.get_keys(),
+            vec!["1", "2", "3", "4", "5", "6", "7", "8", "9"]
+        );
+
+        assert!(obj.rename_key("2", "two"));
+        assert!(obj.rename_key("3", "two"));
+        assert!(!obj.rename_key("3", "three"));
+        assert_eq!(
+            obj.get_keys(),
+            vec!["1", "two", "4", "5", "6", "7", "8", "9"]
+        );
+        assert_eq!(obj["two"], JsonValue::Num(JsonNum::Int(3)));
+        assert!(obj.index.is_none());
+
+        for (_, value) in &mut obj {
+            *value = JsonValue::Null;
+        }
+        assert!(obj.values_mut().all(|value| value.is_null()));
+        obj.extend(vec![("1", JsonValue::Bool(true)), ("10", JsonValue::Null)]);
+        let members: Vec<(String, JsonValue)> = obj.into_iter().take(1).collect();
+        assert_eq!(members, vec![("1".to_owned(), JsonValue::Bool(true))]);
     }
 }
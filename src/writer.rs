@@ -0,0 +1,529 @@
+//! Streaming JSON output to any `io::Write`, without building a DOM.
+
+use std::io::Write;
+
+use super::error::Error;
+use super::serializer::{self, PrettyConfig};
+use super::types::{JsonNum, JsonValue};
+
+enum Frame {
+    Object { has_key: bool },
+    Array,
+}
+
+/// Output that has been checked but not laid out yet.
+enum Piece {
+    Begin(char),
+    End(char),
+    /// An escaped key, without its colon.
+    Key(String),
+    Scalar(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Style {
+    /// Without any whitespace.
+    Compact,
+    /// On one line, with a space after every comma.
+    Inline,
+    /// One member or element per line.
+    Multiline,
+}
+
+/// An array or object that has been begun in the output.
+struct Level {
+    style: Style,
+    object: bool,
+    has_children: bool,
+}
+
+impl Level {
+    /// If `piece`, which comes next inside this array or object, starts a
+    /// member or element, whether there was one before it.
+    fn start_child(&mut self, piece: &Piece) -> Option<bool> {
+        match piece {
+            Piece::End(_) => None,
+            // a value inside an object follows its key
+            Piece::Begin(_) | Piece::Scalar(_) if self.object => None,
+            _ => Some(std::mem::replace(&mut self.has_children, true)),
+        }
+    }
+}
+
+/// An array or object held back until it is known whether it fits on the
+/// line within `max_width`. It is laid out as soon as it gets too wide, so
+/// no more than about a line of output is ever held back.
+struct Pending {
+    pieces: Vec<Piece>,
+    /// The arrays and objects inside it that are not ended yet.
+    levels: Vec<Level>,
+    /// The width of the pieces written on one line.
+    width: usize,
+    /// The columns left on the line where it starts.
+    available: usize,
+}
+
+impl Pending {
+    fn push(&mut self, piece: Piece, colon: usize) {
+        if let Some(level) = self.levels.last_mut() {
+            if level.start_child(&piece) == Some(true) {
+                self.width += 2;
+            }
+        }
+        match &piece {
+            Piece::Begin(begin) => self.levels.push(Level {
+                style: Style::Inline,
+                object: *begin == '{',
+                has_children: false,
+            }),
+            Piece::End(_) => {
+                self.levels.pop();
+            }
+            _ => {}
+        }
+        self.width += match &piece {
+            Piece::Begin(_) | Piece::End(_) => 1,
+            Piece::Key(key) => key.chars().count() + 1 + colon,
+            Piece::Scalar(scalar) => scalar.chars().count(),
+        };
+        self.pieces.push(piece);
+    }
+
+    fn is_ended(&self) -> bool {
+        self.levels.is_empty()
+    }
+}
+
+/// Writes JSON piece by piece, escaping strings and checking that the
+/// calls make up a well formed document, e.g. that every value inside an
+/// object comes after a key and every array or object is ended.
+///
+/// Each piece goes straight to `writer` in a small `write_all`, so wrap
+/// files and sockets in a `BufWriter`. `finish` flushes it. With a
+/// `max_width`, an array or object is held back until it is known whether
+/// it fits on one line.
+pub struct JsonWriter<W: Write> {
+    writer: W,
+    pretty: Option<PrettyConfig>,
+    stack: Vec<Frame>,
+    root_written: bool,
+    levels: Vec<Level>,
+    pending: Option<Pending>,
+    /// The width of the last line written so far.
+    column: usize,
+}
+
+impl<W: Write> JsonWriter<W> {
+    /// A writer producing compact output.
+    pub fn new(writer: W) -> JsonWriter<W> {
+        JsonWriter {
+            writer,
+            pretty: None,
+            stack: vec![],
+            root_written: false,
+            levels: vec![],
+            pending: None,
+            column: 0,
+        }
+    }
+
+    /// A writer producing output laid out like `to_string_pretty`.
+    pub fn pretty(writer: W, config: PrettyConfig) -> JsonWriter<W> {
+        JsonWriter {
+            pretty: Some(config),
+            ..JsonWriter::new(writer)
+        }
+    }
+
+    /// The pretty config, if arrays and objects at `depth` are spread over
+    /// several lines.
+    fn pretty_at(&self, depth: usize) -> Option<&PrettyConfig> {
+        self.pretty
+            .as_ref()
+            .filter(|config| config.recursive || depth <= 1)
+    }
+
+    fn colon(&self) -> &'static str {
+        match &self.pretty {
+            Some(config) if config.space_after_colon => " ",
+            _ => "",
+        }
+    }
+
+    fn put(&mut self, text: &str) -> Result<(), Error> {
+        self.writer.write_all(text.as_bytes())?;
+        self.column = match text.rfind('\n') {
+            Some(newline) => text[newline + 1..].chars().count(),
+            None => self.column + text.chars().count(),
+        };
+        Ok(())
+    }
+
+    fn write_newline(&mut self, depth: usize) -> Result<(), Error> {
+        if let Some(config) = &self.pretty {
+            let mut out = config.newline.as_str().to_string();
+            for _ in 0..depth {
+                out.push_str(&config.indent);
+            }
+            self.put(&out)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that a value may be written here.
+    fn before_value(&mut self) -> Result<(), Error> {
+        match self.stack.last_mut() {
+            None if self.root_written => Err(Error::MultipleRoots),
+            None => {
+                self.root_written = true;
+                Ok(())
+            }
+            Some(Frame::Object { has_key, .. }) => {
+                if !*has_key {
+                    return Err(Error::ValueWithoutKey);
+                }
+                *has_key = false;
+                Ok(())
+            }
+            Some(Frame::Array) => Ok(()),
+        }
+    }
+
+    /// Passes a checked piece on to be laid out, unless an array or object
+    /// is held back, in which case it goes after that.
+    fn emit(&mut self, piece: Piece) -> Result<(), Error> {
+        let mut pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return self.lay_out(piece),
+        };
+
+        if pending.is_ended() {
+            // only now is it known whether a comma follows on the same line
+            let trailing = if let Piece::End(_) = piece { 0 } else { 1 };
+            let fits = pending.width + trailing <= pending.available;
+            self.release(pending, fits)?;
+            return self.emit(piece);
+        }
+
+        pending.push(piece, self.colon().len());
+        if pending.width > pending.available {
+            return self.release(pending, false);
+        }
+        self.pending = Some(pending);
+        Ok(())
+    }
+
+    /// Lays out an array or object that was held back, on one line if it
+    /// `fits` and over several otherwise.
+    fn release(&mut self, pending: Pending, fits: bool) -> Result<(), Error> {
+        let mut pieces = pending.pieces.into_iter();
+        if let Some(Piece::Begin(begin)) = pieces.next() {
+            let style = if fits {
+                Style::Inline
+            } else {
+                Style::Multiline
+            };
+            self.begin_level(begin, style)?;
+        }
+        for piece in pieces {
+            self.emit(piece)?;
+        }
+        Ok(())
+    }
+
+    fn begin_level(&mut self, begin: char, style: Style) -> Result<(), Error> {
+        self.put(begin.encode_utf8(&mut [0; 4]))?;
+        self.levels.push(Level {
+            style,
+            object: begin == '{',
+            has_children: false,
+        });
+        Ok(())
+    }
+
+    fn lay_out(&mut self, piece: Piece) -> Result<(), Error> {
+        let depth = self.levels.len();
+        let parent = self.levels.last().map(|level| level.style);
+        let child = self
+            .levels
+            .last_mut()
+            .and_then(|level| level.start_child(&piece));
+        if child == Some(true) {
+            self.put(if parent == Some(Style::Inline) {
+                ", "
+            } else {
+                ","
+            })?;
+        }
+        if child.is_some() && parent == Some(Style::Multiline) {
+            self.write_newline(depth)?;
+        }
+
+        match piece {
+            Piece::Begin(begin) => {
+                let max_width = self.pretty.as_ref().and_then(|config| config.max_width);
+                let style = match parent {
+                    Some(Style::Inline) => Style::Inline,
+                    _ if self.pretty_at(depth + 1).is_none() => Style::Compact,
+                    _ => match max_width.and_then(|width| width.checked_sub(self.column)) {
+                        Some(available) => {
+                            let mut pending = Pending {
+                                pieces: vec![],
+                                levels: vec![],
+                                width: 0,
+                                available,
+                            };
+                            pending.push(Piece::Begin(begin), self.colon().len());
+                            self.pending = Some(pending);
+                            return Ok(());
+                        }
+                        None => Style::Multiline,
+                    },
+                };
+                self.begin_level(begin, style)
+            }
+            Piece::End(end) => {
+                if let Some(level) = self.levels.pop() {
+                    if level.has_children && level.style == Style::Multiline {
+                        self.write_newline(depth - 1)?;
+                    }
+                }
+                self.put(end.encode_utf8(&mut [0; 4]))
+            }
+            Piece::Key(key) => {
+                let colon = match parent {
+                    Some(Style::Compact) => "",
+                    _ => self.colon(),
+                };
+                self.put(&format!("{}:{}", key, colon))
+            }
+            Piece::Scalar(scalar) => self.put(&scalar),
+        }
+    }
+
+    fn write_scalar(&mut self, raw: String) -> Result<(), Error> {
+        self.before_value()?;
+        self.emit(Piece::Scalar(raw))
+    }
+
+    pub fn begin_object(&mut self) -> Result<(), Error> {
+        self.before_value()?;
+        self.stack.push(Frame::Object { has_key: false });
+        self.emit(Piece::Begin('{'))
+    }
+
+    pub fn end_object(&mut self) -> Result<(), Error> {
+        match self.stack.last() {
+            Some(Frame::Object { has_key: true, .. }) => return Err(Error::KeyWithoutValue),
+            Some(Frame::Object { .. }) => {}
+            _ => return Err(Error::UnbalancedEnd),
+        }
+        self.stack.pop();
+        self.emit(Piece::End('}'))
+    }
+
+    pub fn begin_array(&mut self) -> Result<(), Error> {
+        self.before_value()?;
+        self.stack.push(Frame::Array);
+        self.emit(Piece::Begin('['))
+    }
+
+    pub fn end_array(&mut self) -> Result<(), Error> {
+        match self.stack.last() {
+            Some(Frame::Array) => {}
+            _ => return Err(Error::UnbalancedEnd),
+        }
+        self.stack.pop();
+        self.emit(Piece::End(']'))
+    }
+
+    pub fn key(&mut self, key: &str) -> Result<(), Error> {
+        match self.stack.last_mut() {
+            Some(Frame::Object { has_key: true, .. }) => return Err(Error::KeyWithoutValue),
+            Some(Frame::Object { has_key }) => *has_key = true,
+            _ => return Err(Error::KeyOutsideObject),
+        }
+        let mut out = String::new();
+        serializer::write_escaped_str(&mut out, key);
+        self.emit(Piece::Key(out))
+    }
+
+    pub fn string(&mut self, string: &str) -> Result<(), Error> {
+        let mut out = String::new();
+        serializer::write_escaped_str(&mut out, string);
+        self.write_scalar(out)
+    }
+
+    pub fn i64(&mut self, int: i64) -> Result<(), Error> {
+        self.write_scalar(int.to_string())
+    }
+
+    pub fn f64(&mut self, float: f64) -> Result<(), Error> {
+        let mut out = String::new();
+        serializer::write_num(&mut out, &JsonNum::Float(float))?;
+        self.write_scalar(out)
+    }
+
+    /// Writes a number exactly as it was read. `raw` must already be known
+    /// to be a JSON number.
+    pub(crate) fn number(&mut self, raw: &str) -> Result<(), Error> {
+        self.write_scalar(raw.to_string())
+    }
+
+    pub fn bool(&mut self, bool: bool) -> Result<(), Error> {
+        self.write_scalar(bool.to_string())
+    }
+
+    pub fn null(&mut self) -> Result<(), Error> {
+        self.write_scalar("null".to_string())
+    }
+
+    /// Writes a whole value, with the same checks as writing it piece by piece.
+    pub fn write_value(&mut self, value: &JsonValue) -> Result<(), Error> {
+        match value {
+            JsonValue::String(string) => self.string(string),
+            JsonValue::Num(JsonNum::Int(int)) => self.i64(*int),
+            JsonValue::Num(JsonNum::Float(float)) => self.f64(*float),
+            JsonValue::Bool(bool) => self.bool(*bool),
+            JsonValue::Null => self.null(),
+            JsonValue::Vec(vec) => {
+                self.begin_array()?;
+                for element in vec {
+                    self.write_value(element)?;
+                }
+                self.end_array()
+            }
+            JsonValue::Object(object) => {
+                self.begin_object()?;
+                for (key, value) in object.to_iter() {
+                    self.key(key)?;
+                    self.write_value(value)?;
+                }
+                self.end_object()
+            }
+        }
+    }
+
+    /// Checks that the document is complete, flushes it and returns the
+    /// underlying writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if !self.stack.is_empty() || !self.root_written {
+            return Err(Error::Incomplete);
+        }
+        if let Some(pending) = self.pending.take() {
+            let fits = pending.width <= pending.available;
+            self.release(pending, fits)?;
+        }
+        if let Some(config) = &self.pretty {
+            if config.trailing_newline {
+                self.writer.write_all(config.newline.as_str().as_bytes())?;
+            }
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::to_string_pretty;
+    use crate::test_util::test_files;
+    use std::fs;
+
+    fn finished(writer: JsonWriter<Vec<u8>>) -> String {
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn write_compact_document() {
+        let mut writer = JsonWriter::new(vec![]);
+        writer.begin_object().unwrap();
+        writer.key("na\"me").unwrap();
+        writer.string("line\nbreak").unwrap();
+        writer.key("list").unwrap();
+        writer.begin_array().unwrap();
+        writer.i64(1).unwrap();
+        writer.f64(2.0).unwrap();
+        writer.bool(true).unwrap();
+        writer.null().unwrap();
+        writer.begin_object().unwrap();
+        writer.end_object().unwrap();
+        writer.end_array().unwrap();
+        writer.end_object().unwrap();
+
+        assert_eq!(
+            finished(writer),
+            "{\"na\\\"me\":\"line\\nbreak\",\"list\":[1,2.0,true,null,{}]}"
+        );
+    }
+
+    #[test]
+    fn pretty_matches_serializer() {
+        let json = fs::read_to_string("./test_files/mixed.json").unwrap();
+        let value = JsonValue::Object(crate::parse(json));
+        let config = PrettyConfig {
+            trailing_newline: true,
+            ..PrettyConfig::default()
+        };
+
+        let mut writer = JsonWriter::pretty(vec![], config.clone());
+        writer.write_value(&value).unwrap();
+        assert_eq!(finished(writer), to_string_pretty(&value, &config).unwrap());
+    }
+
+    #[test]
+    fn max_width_matches_serializer() {
+        for (file, json) in test_files() {
+            let value = JsonValue::Object(crate::parse(json));
+            for max_width in 0..100 {
+                let config = PrettyConfig {
+                    max_width: Some(max_width),
+                    recursive: max_width % 3 > 0,
+                    ..PrettyConfig::default()
+                };
+                let mut writer = JsonWriter::pretty(vec![], config.clone());
+                writer.write_value(&value).unwrap();
+                assert_eq!(
+                    finished(writer),
+                    to_string_pretty(&value, &config).unwrap(),
+                    "{} at {}",
+                    file,
+                    max_width
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reject_misuse() {
+        let mut writer = JsonWriter::new(vec![]);
+        writer.begin_object().unwrap();
+        assert!(matches!(writer.i64(1), Err(Error::ValueWithoutKey)));
+        assert!(matches!(writer.end_array(), Err(Error::UnbalancedEnd)));
+        writer.key("a").unwrap();
+        assert!(matches!(
+            writer.f64(f64::NAN),
+            Err(Error::NonFiniteNumber(_))
+        ));
+        assert!(matches!(writer.key("b"), Err(Error::KeyWithoutValue)));
+        assert!(matches!(writer.end_object(), Err(Error::KeyWithoutValue)));
+        writer.begin_array().unwrap();
+        assert!(matches!(writer.key("c"), Err(Error::KeyOutsideObject)));
+        writer.end_array().unwrap();
+        writer.end_object().unwrap();
+        assert!(matches!(writer.null(), Err(Error::MultipleRoots)));
+        assert!(matches!(writer.end_object(), Err(Error::UnbalancedEnd)));
+    }
+
+    #[test]
+    fn reject_incomplete_document() {
+        let mut writer = JsonWriter::new(vec![]);
+        writer.begin_array().unwrap();
+        assert!(matches!(writer.finish(), Err(Error::Incomplete)));
+        assert!(matches!(
+            JsonWriter::new(vec![]).finish(),
+            Err(Error::Incomplete)
+        ));
+    }
+}